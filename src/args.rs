@@ -0,0 +1,122 @@
+use crate::channel_settings::Language;
+
+/// コマンドのオプション
+pub struct FlagSpec {
+    /// `--channel` のような名前 (最初の名前を正式な名前とする)
+    pub names: &'static [&'static str],
+    /// true の場合は次の引数を値として受け取る
+    pub takes_value: bool,
+}
+
+/// parse したオプションと、オプション以外の引数
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Args {
+    /// 正式な名前と値
+    flags: Vec<(&'static str, Option<String>)>,
+    pub positional: Vec<String>,
+}
+impl Args {
+    /// like `-a --user @SSlime --grep "ミーティング 資料" --limit=5`
+    ///
+    /// `"` で囲むと空白を含む値を指定できる
+    pub fn parse(input: &str, specs: &[FlagSpec], language: Language) -> Result<Self, String> {
+        let mut args = Self::default();
+        let mut tokens = split(input, language)?.into_iter();
+        while let Some(token) = tokens.next() {
+            if !token.starts_with('-') || token == "-" {
+                args.positional.push(token);
+                continue;
+            }
+            let (name, inline_value) = match token.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (token, None),
+            };
+            let spec = specs
+                .iter()
+                .find(|spec| spec.names.contains(&name.as_str()))
+                .ok_or_else(|| match language {
+                    Language::Ja => format!("`{}` というオプションはありません", name),
+                    Language::En => format!("Unknown option `{}`", name),
+                })?;
+            let value = match (spec.takes_value, inline_value) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(tokens.next().ok_or_else(|| match language {
+                    Language::Ja => format!("`{}` の値を指定してください", name),
+                    Language::En => format!("Specify a value for `{}`", name),
+                })?),
+                (false, Some(_)) => {
+                    return Err(match language {
+                        Language::Ja => format!("`{}` に値は指定できません", name),
+                        Language::En => format!("`{}` does not take a value", name),
+                    })
+                }
+                (false, None) => None,
+            };
+            args.flags.push((spec.names[0], value));
+        }
+        Ok(args)
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.flags.iter().any(|(flag, _)| *flag == name)
+    }
+
+    /// 複数回指定された場合は最後の値
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(flag, _)| *flag == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// オプションを正式な名前で書き直したもの (ページ送りのコマンドなどに使う)
+    pub fn flags_to_string(&self) -> String {
+        self.flags
+            .iter()
+            .map(|(name, value)| match value {
+                Some(value) if value.is_empty() || value.contains(char::is_whitespace) => {
+                    format!("{} \"{}\"", name, value)
+                }
+                Some(value) => format!("{} {}", name, value),
+                None => name.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// 空白で区切る (`"` で囲まれた部分は区切らない)
+fn split(input: &str, language: Language) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut in_quote = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                in_quote = !in_quote;
+                in_token = true;
+            }
+            c if c.is_whitespace() && !in_quote => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if in_quote {
+        return Err(language
+            .pick("`\"` が閉じられていません", "Unclosed `\"`")
+            .to_string());
+    }
+    if in_token {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
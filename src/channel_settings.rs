@@ -0,0 +1,266 @@
+use std::collections::BTreeSet;
+
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    date::TimeWindow,
+    settings::{invalid_timezone_message, unknown_key_message, Settings, SettingsStore},
+};
+
+/// チャンネルごとに禁止できるコマンド
+///
+/// `purge` (管理者用) と `channel-config` (禁止すると元に戻せなくなる) は禁止できない
+pub const DENIABLE_COMMANDS: [&str; 13] = [
+    "add", "remove", "list", "snooze", "config", "help", "join", "leave", "export", "import",
+    "stats", "history", "redo",
+];
+
+/// traQ の channel id を key に持つチャンネルごとの設定
+pub type ChannelSettingsStore = SettingsStore<ChannelSettings>;
+
+/// BOT が返信するときの言語
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    #[default]
+    Ja,
+    En,
+}
+impl Language {
+    /// 言語に応じてどちらかの文言を返す
+    pub fn pick<'a>(self, ja: &'a str, en: &'a str) -> &'a str {
+        match self {
+            Self::Ja => ja,
+            Self::En => en,
+        }
+    }
+}
+
+/// 静かな時間帯に通知するタイマーの扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuietAction {
+    /// 静かな時間帯が終わるまで通知を遅らせる
+    #[default]
+    Defer,
+    /// チャンネルではなく設定者の DM に通知する
+    Dm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub window: TimeWindow,
+    pub action: QuietAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChannelSettings {
+    /// このチャンネルで使えないコマンド (管理者は使える)
+    pub denied_commands: BTreeSet<String>,
+    /// BOT が参加していても、メンションしたときのみ反応する
+    pub require_mention: bool,
+    pub language: Language,
+    /// 静かな時間帯の解釈に使うタイムゾーン
+    pub timezone: Tz,
+    pub quiet_hours: Option<QuietHours>,
+}
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        Self {
+            denied_commands: BTreeSet::new(),
+            require_mention: false,
+            language: Language::default(),
+            timezone: chrono_tz::Asia::Tokyo,
+            quiet_hours: None,
+        }
+    }
+}
+impl Settings for ChannelSettings {
+    type Update = ChannelSettingUpdate;
+
+    fn apply(&mut self, update: ChannelSettingUpdate) {
+        let default = Self::default();
+        match update {
+            ChannelSettingUpdate::Deny(Some(commands)) => self.denied_commands.extend(commands),
+            ChannelSettingUpdate::Deny(None) => self.denied_commands = default.denied_commands,
+            ChannelSettingUpdate::Allow(commands) => {
+                for command in commands {
+                    self.denied_commands.remove(&command);
+                }
+            }
+            ChannelSettingUpdate::Mention(require_mention) => {
+                self.require_mention = require_mention.unwrap_or(default.require_mention)
+            }
+            ChannelSettingUpdate::Language(language) => {
+                self.language = language.unwrap_or(default.language)
+            }
+            ChannelSettingUpdate::Timezone(timezone) => {
+                self.timezone = timezone.unwrap_or(default.timezone)
+            }
+            ChannelSettingUpdate::Quiet(quiet_hours) => self.quiet_hours = quiet_hours,
+        }
+    }
+}
+impl ChannelSettings {
+    pub fn is_denied(&self, command: &str) -> bool {
+        self.denied_commands.contains(command)
+    }
+
+    /// `channel-config` コマンドで表示する設定の一覧
+    pub fn describe(&self, language: Language) -> String {
+        format!(
+            "|{}|\n|---|---|\n|deny|{}|\n|mention|{}|\n|lang|{}|\n|tz|{}|\n|quiet|{}|",
+            language.pick("項目|値", "Key|Value"),
+            if self.denied_commands.is_empty() {
+                "-".to_string()
+            } else {
+                self.denied_commands
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+            if self.require_mention { "on" } else { "off" },
+            match self.language {
+                Language::Ja => "ja",
+                Language::En => "en",
+            },
+            self.timezone.name(),
+            match self.quiet_hours {
+                Some(QuietHours { window, action }) => format!(
+                    "{} ({})",
+                    window,
+                    match action {
+                        QuietAction::Defer => "defer",
+                        QuietAction::Dm => "dm",
+                    }
+                ),
+                None => "-".to_string(),
+            }
+        )
+    }
+}
+
+/// 設定の変更
+/// None の場合はデフォルトに戻す
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelSettingUpdate {
+    Deny(Option<Vec<String>>),
+    Allow(Vec<String>),
+    Mention(Option<bool>),
+    Language(Option<Language>),
+    Timezone(Option<Tz>),
+    Quiet(Option<QuietHours>),
+}
+impl ChannelSettingUpdate {
+    /// like `deny add list`, `mention on`, `quiet 23:00-08:00 dm`, `quiet reset`
+    pub fn parse(key: &str, value: &str, language: Language) -> Result<Self, String> {
+        let value = if value == "reset" { None } else { Some(value) };
+        match key {
+            "deny" => Ok(Self::Deny(
+                value
+                    .map(|value| parse_commands(value, language))
+                    .transpose()?,
+            )),
+            "allow" => Ok(Self::Allow(
+                value
+                    .map(|value| parse_commands(value, language))
+                    .transpose()?
+                    .ok_or_else(|| {
+                        language
+                            .pick(
+                                "許可するコマンドを指定してください",
+                                "Specify the commands to allow",
+                            )
+                            .to_string()
+                    })?,
+            )),
+            "mention" => Ok(Self::Mention(
+                value
+                    .map(|value| match value {
+                        "on" => Ok(true),
+                        "off" => Ok(false),
+                        _ => Err(match language {
+                            Language::Ja => format!("`{}` は on か off で指定してください", value),
+                            Language::En => format!("`{}` must be on or off", value),
+                        }),
+                    })
+                    .transpose()?,
+            )),
+            "lang" | "language" => Ok(Self::Language(
+                value
+                    .map(|value| match value {
+                        "ja" => Ok(Language::Ja),
+                        "en" => Ok(Language::En),
+                        _ => Err(match language {
+                            Language::Ja => format!("`{}` は ja か en で指定してください", value),
+                            Language::En => format!("`{}` must be ja or en", value),
+                        }),
+                    })
+                    .transpose()?,
+            )),
+            "tz" | "timezone" => Ok(Self::Timezone(
+                value
+                    .map(|value| {
+                        value
+                            .parse()
+                            .map_err(|_| invalid_timezone_message(value, language))
+                    })
+                    .transpose()?,
+            )),
+            "quiet" => Ok(Self::Quiet(
+                value
+                    .map(|value| {
+                        let mut splitted = value.split_whitespace();
+                        let window =
+                            TimeWindow::parse(splitted.next().unwrap_or_default(), language)?;
+                        let action = match splitted.next() {
+                            None | Some("defer") => QuietAction::Defer,
+                            Some("dm") => QuietAction::Dm,
+                            Some(action) => {
+                                return Err(match language {
+                                    Language::Ja => {
+                                        format!("`{}` は defer か dm で指定してください", action)
+                                    }
+                                    Language::En => {
+                                        format!("`{}` must be defer or dm", action)
+                                    }
+                                })
+                            }
+                        };
+                        Ok(QuietHours { window, action })
+                    })
+                    .transpose()?,
+            )),
+            _ => Err(unknown_key_message(key, language)),
+        }
+    }
+}
+
+/// 空白かカンマ区切りのコマンド名
+fn parse_commands(value: &str, language: Language) -> Result<Vec<String>, String> {
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|command| !command.is_empty())
+        .map(|command| {
+            if DENIABLE_COMMANDS.contains(&command) {
+                Ok(command.to_string())
+            } else {
+                Err(match language {
+                    Language::Ja => format!(
+                        "`{}` は指定できません ({} のいずれかで指定してください)",
+                        command,
+                        DENIABLE_COMMANDS.join(", ")
+                    ),
+                    Language::En => format!(
+                        "`{}` cannot be specified (must be one of {})",
+                        command,
+                        DENIABLE_COMMANDS.join(", ")
+                    ),
+                })
+            }
+        })
+        .collect()
+}
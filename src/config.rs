@@ -0,0 +1,151 @@
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use chrono::NaiveTime;
+
+use crate::{date::TimeOfDay, holiday::Holidays};
+
+/// 環境変数から読み込む BOT 全体の設定
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 他人のタイマーの削除など、所有者チェックを無視した操作ができる traQ ユーザー名
+    admins: HashSet<String>,
+    /// 1ユーザーが同時に設定できるタイマーの数
+    pub max_timers_per_user: usize,
+    /// 1チャンネルに同時に設定できるタイマーの数
+    pub max_timers_per_channel: usize,
+    /// タイマーに設定できる最大の時間
+    pub max_timer_duration: Duration,
+    /// `add_rate_limit_interval` の間に 1ユーザーが実行できる add コマンドの回数
+    pub add_rate_limit: usize,
+    pub add_rate_limit_interval: Duration,
+    /// 朝, 昼, 夕方, 夜 が指す時刻
+    pub time_of_day: TimeOfDay,
+    /// 設定などを保存するディレクトリ
+    pub data_dir: PathBuf,
+    /// `--skip-holidays` で使う祝日の一覧
+    pub holidays: Holidays,
+    /// 終了するときに、処理中の操作や通知を待つ最大の時間
+    pub shutdown_timeout: Duration,
+    /// `/healthz`, `/readyz` に答える HTTP サーバーのアドレス (None の場合は起動しない)
+    pub health_addr: Option<SocketAddr>,
+    /// `stats` コマンド用のタイマーの記録を残す期間
+    pub stats_retention: Duration,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            admins: HashSet::new(),
+            max_timers_per_user: 50,
+            max_timers_per_channel: 200,
+            max_timer_duration: Duration::from_secs(60 * 60 * 24 * 365),
+            add_rate_limit: 10,
+            add_rate_limit_interval: Duration::from_secs(60),
+            time_of_day: TimeOfDay::default(),
+            data_dir: PathBuf::from("data"),
+            holidays: Holidays::default(),
+            shutdown_timeout: Duration::from_secs(10),
+            health_addr: None,
+            stats_retention: Duration::from_secs(60 * 60 * 24 * 90),
+        }
+    }
+}
+impl Config {
+    /// `ADMIN_USERS` (カンマ区切りの traQ ID) などから設定を読み込む
+    /// 指定されていない値は [`Config::default`] の値になる
+    pub fn from_env() -> Self {
+        let default = Self::default();
+
+        let admins = std::env::var("ADMIN_USERS")
+            .map(|users| {
+                users
+                    .split(',')
+                    .map(|user| user.trim().trim_start_matches('@').to_string())
+                    .filter(|user| !user.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            admins,
+            max_timers_per_user: env_or("MAX_TIMERS_PER_USER", default.max_timers_per_user),
+            max_timers_per_channel: env_or(
+                "MAX_TIMERS_PER_CHANNEL",
+                default.max_timers_per_channel,
+            ),
+            max_timer_duration: Duration::from_secs(
+                env_or(
+                    "MAX_TIMER_DAYS",
+                    default.max_timer_duration.as_secs() / (60 * 60 * 24),
+                )
+                .saturating_mul(60 * 60 * 24),
+            ),
+            add_rate_limit: env_or("ADD_RATE_LIMIT", default.add_rate_limit),
+            add_rate_limit_interval: Duration::from_secs(env_or(
+                "ADD_RATE_LIMIT_SECONDS",
+                default.add_rate_limit_interval.as_secs(),
+            )),
+            time_of_day: TimeOfDay {
+                morning: env_time_or("TIME_OF_MORNING", default.time_of_day.morning),
+                noon: env_time_or("TIME_OF_NOON", default.time_of_day.noon),
+                evening: env_time_or("TIME_OF_EVENING", default.time_of_day.evening),
+                night: env_time_or("TIME_OF_NIGHT", default.time_of_day.night),
+            },
+            data_dir: std::env::var("DATA_DIR")
+                .map(PathBuf::from)
+                .unwrap_or(default.data_dir),
+            holidays: std::env::var("HOLIDAYS_FILE")
+                .map(|path| Holidays::load(Path::new(&path)))
+                .unwrap_or(default.holidays),
+            shutdown_timeout: Duration::from_secs(env_or(
+                "SHUTDOWN_TIMEOUT_SECONDS",
+                default.shutdown_timeout.as_secs(),
+            )),
+            health_addr: std::env::var("HEALTH_ADDR").ok().and_then(|addr| {
+                addr.parse()
+                    .map_err(|_| log::warn!("Failed to parse HEALTH_ADDR: {:?}", addr))
+                    .ok()
+            }),
+            stats_retention: Duration::from_secs(
+                env_or(
+                    "STATS_RETENTION_DAYS",
+                    default.stats_retention.as_secs() / (60 * 60 * 24),
+                )
+                .saturating_mul(60 * 60 * 24),
+            ),
+        }
+    }
+
+    pub fn is_admin(&self, user_name: &str) -> bool {
+        self.admins.contains(user_name)
+    }
+}
+
+/// 環境変数 `key` を parse して返す
+/// 設定されていないか parse できない場合は `default` を返す
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            log::warn!("Failed to parse {}: {:?}", key, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// 環境変数 `key` を `HH:MM` 形式の時刻として parse して返す
+/// 設定されていないか parse できない場合は `default` を返す
+fn env_time_or(key: &str, default: NaiveTime) -> NaiveTime {
+    match std::env::var(key) {
+        Ok(value) => NaiveTime::parse_from_str(&value, "%H:%M").unwrap_or_else(|_| {
+            log::warn!("Failed to parse {}: {:?}", key, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
@@ -0,0 +1,536 @@
+use std::fmt;
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::channel_settings::Language;
+
+/// 朝, 昼, 夕方, 夜 といった曖昧な時刻表現が指す時刻
+#[derive(Debug, Clone)]
+pub struct TimeOfDay {
+    pub morning: NaiveTime,
+    pub noon: NaiveTime,
+    pub evening: NaiveTime,
+    pub night: NaiveTime,
+}
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            morning: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            noon: NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+            evening: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+            night: NaiveTime::from_hms_opt(20, 0, 0).unwrap(),
+        }
+    }
+}
+
+/// like 23:00-08:00
+///
+/// 毎日の `start` から `end` までの時間帯 (日をまたいでもよい)
+/// `start` は含み、`end` は含まない
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+impl TimeWindow {
+    /// like 23:00-08:00, 22時-7時半
+    pub fn parse(input: &str, language: Language) -> Result<Self, String> {
+        let error = || match language {
+            Language::Ja => format!("`{}` は `23:00-08:00` の形式で指定してください", input),
+            Language::En => format!("`{}` must be in the form of `23:00-08:00`", input),
+        };
+        let (start, end) = input.split_once('-').ok_or_else(error)?;
+        let parse_time = |input: &str| match parse_clock(input.trim()) {
+            Some((hour, minute, "")) => NaiveTime::from_hms_opt(hour, minute, 0),
+            _ => None,
+        };
+        let start = parse_time(start).ok_or_else(error)?;
+        let end = parse_time(end).ok_or_else(error)?;
+        if start == end {
+            return Err(language
+                .pick(
+                    "開始時刻と終了時刻は別の時刻を指定してください",
+                    "The start and end times must be different",
+                )
+                .to_string());
+        }
+        Ok(Self { start, end })
+    }
+
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+
+    /// `now` が時間帯に含まれる場合は、時間帯が終わる日時を返す
+    pub fn end_after(&self, now: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        if !self.contains(now.time()) {
+            return None;
+        }
+        let today = now.date_naive();
+        let date = if now.time() < self.end {
+            today
+        } else {
+            today.succ_opt()?
+        };
+        // 終了時刻が存在しない (夏時間の開始など) 場合は 1時間後とみなす
+        Some(
+            now.timezone()
+                .from_local_datetime(&date.and_time(self.end))
+                .earliest()
+                .unwrap_or(now + chrono::Duration::hours(1)),
+        )
+    }
+}
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DatePart {
+    /// 今日からの日数
+    Relative(u64),
+    MonthEnd,
+    /// 今日以降で最も近いその曜日
+    Weekday(Weekday),
+    ThisWeek(Weekday),
+    NextWeek(Weekday),
+    /// (月, 日)
+    MonthDay(u32, u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Period {
+    Morning,
+    Noon,
+    Evening,
+    Night,
+    Am,
+    Pm,
+}
+
+const RELATIVE_DAYS: [(&str, u64); 7] = [
+    ("今日", 0),
+    ("きょう", 0),
+    ("明日", 1),
+    ("あした", 1),
+    ("あす", 1),
+    ("明後日", 2),
+    ("あさって", 2),
+];
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("月曜", Weekday::Mon),
+    ("火曜", Weekday::Tue),
+    ("水曜", Weekday::Wed),
+    ("木曜", Weekday::Thu),
+    ("金曜", Weekday::Fri),
+    ("土曜", Weekday::Sat),
+    ("日曜", Weekday::Sun),
+];
+
+const PERIODS: [(&str, Period); 6] = [
+    ("朝", Period::Morning),
+    ("昼", Period::Noon),
+    ("夕方", Period::Evening),
+    ("夜", Period::Night),
+    ("午前", Period::Am),
+    ("午後", Period::Pm),
+];
+
+/// like 明日の朝9時, 来週月曜 10:00, 今日の夕方, 月末, 金曜の17時, 12月24日 19:30
+///
+/// 入力の先頭から日時を読み取り、`now` 以降の日時と残りの文字列を返す
+/// 日時の表現として読み取れない場合は `Ok(None)` を返す
+pub fn parse_date<'a>(
+    input: &'a str,
+    now: DateTime<Tz>,
+    time_of_day: &TimeOfDay,
+    language: Language,
+) -> Result<Option<(DateTime<Tz>, &'a str)>, String> {
+    let (date, rest) = match parse_date_part(input) {
+        Some((date, rest)) => (Some(date), rest),
+        None => (None, input),
+    };
+
+    // 日付と時刻の間には 「の」 か空白を入れられる
+    // 空白の後が時刻として読み取れない場合はメッセージとみなす
+    let time_input = rest.strip_prefix('の').unwrap_or(rest);
+    let time_input = if date.is_some() {
+        time_input.trim_start()
+    } else {
+        time_input
+    };
+    let (time, rest) = match parse_time_part(time_input, time_of_day) {
+        Some((time, time_rest)) if is_end_of_date(time_rest) => (Some(time), time_rest),
+        _ => (None, rest),
+    };
+
+    if date.is_none() && time.is_none() {
+        return Ok(None);
+    }
+    if !is_end_of_date(rest) {
+        return Ok(None);
+    }
+    let rest = rest.strip_prefix('に').unwrap_or(rest);
+
+    let today = now.date_naive();
+    let time = time.unwrap_or(time_of_day.morning);
+    let resolve = |date: NaiveDate| -> Result<DateTime<Tz>, String> {
+        now.timezone()
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .ok_or_else(|| match language {
+                Language::Ja => format!("{} {} は存在しない時刻です", date, time),
+                Language::En => format!("{} {} does not exist", date, time),
+            })
+    };
+
+    let no_such_date = |month: u32, day: u32| match language {
+        Language::Ja => format!("{}月{}日 は存在しない日付です", month, day),
+        Language::En => format!("{}/{} does not exist", month, day),
+    };
+    let datetime = match date {
+        None => {
+            let datetime = resolve(today)?;
+            if datetime > now {
+                datetime
+            } else {
+                resolve(add_days(today, 1, language)?)?
+            }
+        }
+        Some(DatePart::Relative(days)) => resolve(add_days(today, days, language)?)?,
+        Some(DatePart::MonthEnd) => {
+            let datetime = resolve(month_end(today.year(), today.month(), language)?)?;
+            if datetime > now {
+                datetime
+            } else {
+                let (year, month) = next_month(today.year(), today.month());
+                resolve(month_end(year, month, language)?)?
+            }
+        }
+        Some(DatePart::Weekday(weekday)) => {
+            let days = days_until(today.weekday(), weekday);
+            let datetime = resolve(add_days(today, days, language)?)?;
+            if datetime > now {
+                datetime
+            } else {
+                resolve(add_days(today, days + 7, language)?)?
+            }
+        }
+        Some(DatePart::ThisWeek(weekday)) => {
+            let monday = today - Days::new(today.weekday().num_days_from_monday() as u64);
+            resolve(add_days(
+                monday,
+                weekday.num_days_from_monday() as u64,
+                language,
+            )?)?
+        }
+        Some(DatePart::NextWeek(weekday)) => {
+            let monday = today - Days::new(today.weekday().num_days_from_monday() as u64);
+            resolve(add_days(
+                monday,
+                7 + weekday.num_days_from_monday() as u64,
+                language,
+            )?)?
+        }
+        Some(DatePart::MonthDay(month, day)) => {
+            let date = NaiveDate::from_ymd_opt(today.year(), month, day)
+                .ok_or_else(|| no_such_date(month, day))?;
+            let datetime = resolve(date)?;
+            if datetime > now {
+                datetime
+            } else {
+                let date = NaiveDate::from_ymd_opt(today.year() + 1, month, day)
+                    .ok_or_else(|| no_such_date(month, day))?;
+                resolve(date)?
+            }
+        }
+    };
+
+    if datetime <= now {
+        return Err(match language {
+            Language::Ja => format!("{} は過去の日時です", format_datetime(datetime)),
+            Language::En => format!("{} is in the past", datetime.format("%Y-%m-%d (%a) %H:%M")),
+        });
+    }
+
+    Ok(Some((datetime, rest)))
+}
+
+/// like 2023-04-01 (土) 09:00
+pub fn format_datetime<T: TimeZone>(datetime: DateTime<T>) -> String
+where
+    T::Offset: std::fmt::Display,
+{
+    let weekday = match datetime.weekday() {
+        Weekday::Mon => "月",
+        Weekday::Tue => "火",
+        Weekday::Wed => "水",
+        Weekday::Thu => "木",
+        Weekday::Fri => "金",
+        Weekday::Sat => "土",
+        Weekday::Sun => "日",
+    };
+    format!(
+        "{} ({}) {}",
+        datetime.format("%Y-%m-%d"),
+        weekday,
+        datetime.format("%H:%M")
+    )
+}
+
+/// 日時の表現の直後 (「に」 を除いて空白か入力の終わり) かどうか
+fn is_end_of_date(rest: &str) -> bool {
+    let rest = rest.strip_prefix('に').unwrap_or(rest);
+    rest.is_empty() || rest.starts_with(char::is_whitespace)
+}
+
+fn parse_date_part(input: &str) -> Option<(DatePart, &str)> {
+    if let Some(rest) = input.strip_prefix("月末") {
+        return Some((DatePart::MonthEnd, rest));
+    }
+    for (word, days) in RELATIVE_DAYS.iter() {
+        if let Some(rest) = input.strip_prefix(word) {
+            return Some((DatePart::Relative(*days), rest));
+        }
+    }
+    if let Some(rest) = input.strip_prefix("今週") {
+        let (weekday, rest) = parse_weekday(rest.strip_prefix('の').unwrap_or(rest))?;
+        return Some((DatePart::ThisWeek(weekday), rest));
+    }
+    if let Some(rest) = input.strip_prefix("来週") {
+        let (weekday, rest) = parse_weekday(rest.strip_prefix('の').unwrap_or(rest))?;
+        return Some((DatePart::NextWeek(weekday), rest));
+    }
+    if let Some((weekday, rest)) = parse_weekday(input) {
+        return Some((DatePart::Weekday(weekday), rest));
+    }
+
+    // M月D日, M/D
+    let (month, rest) = parse_number(input)?;
+    let rest = rest.strip_prefix('月').or_else(|| rest.strip_prefix('/'))?;
+    let (day, rest) = parse_number(rest)?;
+    let rest = rest.strip_prefix('日').unwrap_or(rest);
+    Some((DatePart::MonthDay(month, day), rest))
+}
+
+fn parse_weekday(input: &str) -> Option<(Weekday, &str)> {
+    WEEKDAYS.iter().find_map(|(word, weekday)| {
+        let rest = input.strip_prefix(word)?;
+        Some((*weekday, rest.strip_prefix('日').unwrap_or(rest)))
+    })
+}
+
+/// like 9時, 9時30分, 9時半, 9:30, 朝, 夜9時, 午後3時
+fn parse_time_part<'a>(input: &'a str, time_of_day: &TimeOfDay) -> Option<(NaiveTime, &'a str)> {
+    let (period, rest) = match PERIODS
+        .iter()
+        .find_map(|(word, period)| Some((*period, input.strip_prefix(word)?)))
+    {
+        Some((period, rest)) => (Some(period), rest),
+        None => (None, input),
+    };
+
+    let Some((hour, minute, rest)) = parse_clock(rest) else {
+        let time = match period? {
+            Period::Morning => time_of_day.morning,
+            Period::Noon => time_of_day.noon,
+            Period::Evening => time_of_day.evening,
+            Period::Night => time_of_day.night,
+            Period::Am | Period::Pm => return None,
+        };
+        return Some((time, rest));
+    };
+
+    let hour = match period {
+        Some(Period::Am | Period::Morning) if hour == 12 => 0,
+        Some(Period::Noon) if hour < 6 => hour + 12,
+        Some(Period::Pm | Period::Evening | Period::Night) if hour < 12 => hour + 12,
+        _ => hour,
+    };
+    Some((NaiveTime::from_hms_opt(hour, minute, 0)?, rest))
+}
+
+/// like 9時, 9時30分, 9時半, 9:30
+fn parse_clock(input: &str) -> Option<(u32, u32, &str)> {
+    let (hour, rest) = parse_number(input)?;
+    if let Some(rest) = rest.strip_prefix(':') {
+        let (minute, rest) = parse_number(rest)?;
+        return Some((hour, minute, rest));
+    }
+
+    let rest = rest.strip_prefix('時')?;
+    if let Some(rest) = rest.strip_prefix('半') {
+        return Some((hour, 30, rest));
+    }
+    if let Some((minute, minute_rest)) = parse_number(rest) {
+        if let Some(rest) = minute_rest.strip_prefix('分') {
+            return Some((hour, minute, rest));
+        }
+    }
+    Some((hour, 0, rest))
+}
+
+/// 先頭の 1~2 桁の数字を読み取る
+fn parse_number(input: &str) -> Option<(u32, &str)> {
+    let len = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    if len == 0 || len > 2 {
+        return None;
+    }
+    Some((input[..len].parse().ok()?, &input[len..]))
+}
+
+fn add_days(date: NaiveDate, days: u64, language: Language) -> Result<NaiveDate, String> {
+    date.checked_add_days(Days::new(days))
+        .ok_or_else(|| date_too_large(language))
+}
+
+fn next_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    }
+}
+
+fn month_end(year: i32, month: u32, language: Language) -> Result<NaiveDate, String> {
+    let (year, month) = next_month(year, month);
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|date| date.pred_opt())
+        .ok_or_else(|| date_too_large(language))
+}
+
+fn date_too_large(language: Language) -> String {
+    language
+        .pick("日付が大きすぎます", "The date is too far in the future")
+        .to_string()
+}
+
+/// `from` から次の `to` までの日数 (同じ曜日の場合は 0)
+fn days_until(from: Weekday, to: Weekday) -> u64 {
+    (7 + to.num_days_from_monday() as u64 - from.num_days_from_monday() as u64) % 7
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Asia::Tokyo;
+
+    use super::*;
+    use crate::duration::parse_duration;
+
+    fn datetime(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Tz> {
+        Tokyo
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn parse(input: &str, now: DateTime<Tz>) -> Result<Option<(DateTime<Tz>, &str)>, String> {
+        parse_date(input, now, &TimeOfDay::default(), Language::Ja)
+    }
+
+    /// 2024-05-15 (水) 18:30
+    fn now() -> DateTime<Tz> {
+        datetime(2024, 5, 15, 18, 30)
+    }
+
+    #[test]
+    fn tomorrow_morning() {
+        assert_eq!(
+            parse("明日の朝9時", now()),
+            Ok(Some((datetime(2024, 5, 16, 9, 0), "")))
+        );
+        assert_eq!(
+            parse("明日の朝 資料", now()),
+            Ok(Some((datetime(2024, 5, 16, 9, 0), " 資料")))
+        );
+    }
+
+    #[test]
+    fn next_week() {
+        assert_eq!(
+            parse("来週月曜 10:00 会議", now()),
+            Ok(Some((datetime(2024, 5, 20, 10, 0), " 会議")))
+        );
+    }
+
+    #[test]
+    fn past_time() {
+        assert!(parse("今日の夕方", now()).is_err());
+        assert_eq!(
+            parse("今日の夕方", datetime(2024, 5, 15, 16, 0)),
+            Ok(Some((datetime(2024, 5, 15, 17, 0), "")))
+        );
+    }
+
+    #[test]
+    fn month_end_on_last_day() {
+        let now = datetime(2024, 5, 31, 18, 30);
+        assert_eq!(
+            parse("月末", now),
+            Ok(Some((datetime(2024, 6, 30, 9, 0), "")))
+        );
+        assert_eq!(
+            parse("月末の夜", now),
+            Ok(Some((datetime(2024, 5, 31, 20, 0), "")))
+        );
+    }
+
+    #[test]
+    fn weekday() {
+        assert_eq!(
+            parse("金曜の17時", now()),
+            Ok(Some((datetime(2024, 5, 17, 17, 0), "")))
+        );
+        // 当日の時刻を過ぎている場合は翌週
+        assert_eq!(
+            parse("金曜の17時", datetime(2024, 5, 17, 18, 0)),
+            Ok(Some((datetime(2024, 5, 24, 17, 0), "")))
+        );
+    }
+
+    #[test]
+    fn durations_are_not_dates() {
+        let max = std::time::Duration::MAX;
+        for input in ["1時間半", "3分", "2日後", "1h30m 資料"] {
+            assert_eq!(parse(input, now()), Ok(None), "{}", input);
+            assert!(parse_duration(input, max).is_ok(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn window_across_midnight() {
+        let window = TimeWindow::parse("23:00-08:00", Language::Ja).unwrap();
+        assert_eq!(TimeWindow::parse("23時-8時", Language::Ja), Ok(window));
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        assert!(window.contains(time(23, 0)));
+        assert!(window.contains(time(0, 0)));
+        assert!(window.contains(time(7, 59)));
+        assert!(!window.contains(time(8, 0)));
+        assert!(!window.contains(time(12, 0)));
+
+        assert_eq!(
+            window.end_after(datetime(2024, 5, 15, 23, 30)),
+            Some(datetime(2024, 5, 16, 8, 0))
+        );
+        assert_eq!(
+            window.end_after(datetime(2024, 5, 16, 7, 0)),
+            Some(datetime(2024, 5, 16, 8, 0))
+        );
+        assert_eq!(window.end_after(datetime(2024, 5, 16, 12, 0)), None);
+    }
+}
@@ -0,0 +1,559 @@
+use std::{fmt, time::Duration};
+
+use crate::channel_settings::Language;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Unit {
+    Week,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+impl Unit {
+    fn millis(self) -> u64 {
+        match self {
+            Self::Week => 1000 * 60 * 60 * 24 * 7,
+            Self::Day => 1000 * 60 * 60 * 24,
+            Self::Hour => 1000 * 60 * 60,
+            Self::Minute => 1000 * 60,
+            Self::Second => 1000,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Week => "週",
+            Self::Day => "日",
+            Self::Hour => "時間",
+            Self::Minute => "分",
+            Self::Second => "秒",
+        }
+    }
+
+    fn short_name(self) -> &'static str {
+        match self {
+            Self::Week => "w",
+            Self::Day => "d",
+            Self::Hour => "h",
+            Self::Minute => "m",
+            Self::Second => "s",
+        }
+    }
+}
+
+/// 単位として受け付ける表記
+///
+/// 英字の表記は直後に英字が続かない場合のみ単位とみなす (`5 mango` を 5分にしないため)
+const UNIT_NAMES: [(&str, Unit); 27] = [
+    ("w", Unit::Week),
+    ("week", Unit::Week),
+    ("weeks", Unit::Week),
+    ("週", Unit::Week),
+    ("週間", Unit::Week),
+    ("d", Unit::Day),
+    ("day", Unit::Day),
+    ("days", Unit::Day),
+    ("日", Unit::Day),
+    ("h", Unit::Hour),
+    ("hr", Unit::Hour),
+    ("hrs", Unit::Hour),
+    ("hour", Unit::Hour),
+    ("hours", Unit::Hour),
+    ("時間", Unit::Hour),
+    ("m", Unit::Minute),
+    ("min", Unit::Minute),
+    ("mins", Unit::Minute),
+    ("minute", Unit::Minute),
+    ("minutes", Unit::Minute),
+    ("分", Unit::Minute),
+    ("s", Unit::Second),
+    ("sec", Unit::Second),
+    ("secs", Unit::Second),
+    ("second", Unit::Second),
+    ("seconds", Unit::Second),
+    ("秒", Unit::Second),
+];
+
+/// 単位の直後につけるとその単位の半分を足す (1時間半 など)
+const HALF: char = '半';
+/// 時間の最後につけられる (2日後 など)
+const LATER: char = '後';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseDurationErrorKind {
+    Empty,
+    Negative,
+    ExpectedNumber,
+    ExpectedUnit,
+    UnexpectedChar(char),
+    DuplicatedUnit(Unit),
+    UnorderedUnit,
+    Overflow,
+    Zero,
+    /// 指定できる最大の時間
+    TooLong(Duration),
+}
+
+/// 入力のどこが不正だったかを保持するエラー
+///
+/// `start..end` は入力の文字単位の位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDurationError {
+    input: String,
+    start: usize,
+    end: usize,
+    pub kind: ParseDurationErrorKind,
+}
+impl ParseDurationError {
+    /// 入力のうち、エラー箇所を含む空白区切りの部分までを保持する
+    fn new(chars: &[char], start: usize, end: usize, kind: ParseDurationErrorKind) -> Self {
+        let input_end = chars[end.min(chars.len())..]
+            .iter()
+            .position(|c| c.is_whitespace())
+            .map_or(chars.len(), |len| end + len);
+        Self {
+            input: chars[..input_end].iter().collect(),
+            start,
+            end,
+            kind,
+        }
+    }
+}
+impl ParseDurationError {
+    /// エラー箇所を `^` で示したメッセージ
+    pub fn describe(&self, language: Language) -> String {
+        let message = match (&self.kind, language) {
+            (ParseDurationErrorKind::Empty, Language::Ja) => "時間を指定してください".to_string(),
+            (ParseDurationErrorKind::Empty, Language::En) => "Specify a duration".to_string(),
+            (ParseDurationErrorKind::Negative, Language::Ja) => {
+                "負の時間は指定できません".to_string()
+            }
+            (ParseDurationErrorKind::Negative, Language::En) => {
+                "Negative durations are not allowed".to_string()
+            }
+            (ParseDurationErrorKind::ExpectedNumber, Language::Ja) => "数字が必要です".to_string(),
+            (ParseDurationErrorKind::ExpectedNumber, Language::En) => {
+                "Expected a number".to_string()
+            }
+            (ParseDurationErrorKind::ExpectedUnit, Language::Ja) => {
+                "単位 (w, d, h, m, s, 分 など) が必要です".to_string()
+            }
+            (ParseDurationErrorKind::ExpectedUnit, Language::En) => {
+                "Expected a unit (w, d, h, m, s, min, ...)".to_string()
+            }
+            (ParseDurationErrorKind::UnexpectedChar(c), Language::Ja) => {
+                format!("不正な文字 `{}` があります", c)
+            }
+            (ParseDurationErrorKind::UnexpectedChar(c), Language::En) => {
+                format!("Unexpected character `{}`", c)
+            }
+            (ParseDurationErrorKind::DuplicatedUnit(unit), Language::Ja) => {
+                format!("{}は1つだけ指定してください", unit.name())
+            }
+            (ParseDurationErrorKind::DuplicatedUnit(unit), Language::En) => {
+                format!("`{}` can only be specified once", unit.short_name())
+            }
+            (ParseDurationErrorKind::UnorderedUnit, Language::Ja) => {
+                "単位は 週, 日, 時間, 分, 秒 の順に指定してください".to_string()
+            }
+            (ParseDurationErrorKind::UnorderedUnit, Language::En) => {
+                "Units must be in the order of w, d, h, m, s".to_string()
+            }
+            (ParseDurationErrorKind::Overflow, Language::Ja) => "値が大きすぎます".to_string(),
+            (ParseDurationErrorKind::Overflow, Language::En) => {
+                "The value is too large".to_string()
+            }
+            (ParseDurationErrorKind::Zero, Language::Ja) => {
+                "0 より長い時間を指定してください".to_string()
+            }
+            (ParseDurationErrorKind::Zero, Language::En) => {
+                "The duration must be longer than 0".to_string()
+            }
+            (ParseDurationErrorKind::TooLong(max), Language::Ja) => format!(
+                "{} 日より長い時間は指定できません",
+                max.as_millis() / Unit::Day.millis() as u128
+            ),
+            (ParseDurationErrorKind::TooLong(max), Language::En) => format!(
+                "The duration must not be longer than {} days",
+                max.as_millis() / Unit::Day.millis() as u128
+            ),
+        };
+        let header = language.pick("不正な時間です", "Invalid duration");
+
+        if self.input.is_empty() {
+            return format!("{}: {}", header, message);
+        }
+        let chars = self.input.chars().collect::<Vec<_>>();
+        let column = |pos: usize| {
+            chars[..pos.min(chars.len())]
+                .iter()
+                .map(width)
+                .sum::<usize>()
+        };
+        let (start, end) = (column(self.start), column(self.end));
+        format!(
+            "{}: {}\n```\n{}\n{}{}\n```",
+            header,
+            message,
+            self.input,
+            " ".repeat(start),
+            "^".repeat((end - start).max(1))
+        )
+    }
+}
+impl fmt::Display for ParseDurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.describe(Language::Ja))
+    }
+}
+
+/// 等幅フォントでの表示幅 (ASCII 以外は全角とみなす)
+fn width(c: &char) -> usize {
+    if c.is_ascii() {
+        1
+    } else {
+        2
+    }
+}
+
+/// like 1w2d3h4m5s, 1時間半, 90sec, 1.5h, 5 min, 2日後
+///
+/// 入力の先頭から時間を読み取り、時間と残りの文字列を返す
+/// 数字と単位の間には空白を入れられるが、単位の後に空白が来た時点で時間の終わりとみなす
+///
+/// 単位は 週, 日, 時間, 分, 秒 の順にそれぞれ 1回まで指定でき、合計が 0 より大きく `max` 以下である必要がある
+pub fn parse_duration(input: &str, max: Duration) -> Result<(Duration, &str), ParseDurationError> {
+    let chars = input.chars().collect::<Vec<_>>();
+    let error = |start, end, kind| ParseDurationError::new(&chars, start, end, kind);
+
+    if chars.is_empty() {
+        return Err(error(0, 0, ParseDurationErrorKind::Empty));
+    }
+    if chars[0] == '-' {
+        return Err(error(0, 1, ParseDurationErrorKind::Negative));
+    }
+
+    let mut total: u64 = 0;
+    let mut last_unit: Option<Unit> = None;
+    let mut pos = 0;
+    loop {
+        let start = pos;
+
+        let int_end = digits_end(&chars, pos);
+        if int_end == pos {
+            let kind = if unit_at(&chars, pos).is_some() {
+                ParseDurationErrorKind::ExpectedNumber
+            } else {
+                ParseDurationErrorKind::UnexpectedChar(chars[pos])
+            };
+            return Err(error(pos, pos + 1, kind));
+        }
+        let int_part = &chars[pos..int_end];
+        pos = int_end;
+        let mut frac_part: &[char] = &[];
+        if chars.get(pos) == Some(&'.') {
+            let frac_end = digits_end(&chars, pos + 1);
+            if frac_end == pos + 1 {
+                return Err(error(pos, pos + 1, ParseDurationErrorKind::ExpectedNumber));
+            }
+            frac_part = &chars[pos + 1..frac_end];
+            pos = frac_end;
+        }
+
+        while chars.get(pos).is_some_and(|c| c.is_whitespace()) {
+            pos += 1;
+        }
+
+        let Some((unit, unit_len)) = unit_at(&chars, pos) else {
+            return Err(error(pos, pos + 1, ParseDurationErrorKind::ExpectedUnit));
+        };
+        match last_unit {
+            Some(last_unit) if last_unit == unit => {
+                return Err(error(
+                    start,
+                    pos + unit_len,
+                    ParseDurationErrorKind::DuplicatedUnit(unit),
+                ));
+            }
+            Some(last_unit) if last_unit > unit => {
+                return Err(error(
+                    start,
+                    pos + unit_len,
+                    ParseDurationErrorKind::UnorderedUnit,
+                ));
+            }
+            _ => {}
+        }
+        last_unit = Some(unit);
+        pos += unit_len;
+
+        let is_half = chars.get(pos) == Some(&HALF);
+        if is_half {
+            pos += 1;
+        }
+
+        total = component_millis(int_part, frac_part, unit, is_half)
+            .and_then(|millis| total.checked_add(millis))
+            .ok_or_else(|| error(start, pos, ParseDurationErrorKind::Overflow))?;
+
+        match chars.get(pos) {
+            None => break,
+            Some(c) if c.is_whitespace() => break,
+            Some(&LATER) => {
+                pos += 1;
+                match chars.get(pos) {
+                    None => break,
+                    Some(c) if c.is_whitespace() => break,
+                    Some(&c) => {
+                        return Err(error(
+                            pos,
+                            pos + 1,
+                            ParseDurationErrorKind::UnexpectedChar(c),
+                        ))
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_digit() => continue,
+            Some(&c) => {
+                return Err(error(
+                    pos,
+                    pos + 1,
+                    ParseDurationErrorKind::UnexpectedChar(c),
+                ));
+            }
+        }
+    }
+
+    let duration = Duration::from_millis(total);
+    if duration.is_zero() {
+        return Err(error(0, pos, ParseDurationErrorKind::Zero));
+    }
+    if duration > max {
+        return Err(error(0, pos, ParseDurationErrorKind::TooLong(max)));
+    }
+
+    let rest_index = input
+        .char_indices()
+        .nth(pos)
+        .map_or(input.len(), |(index, _)| index);
+    Ok((duration, &input[rest_index..]))
+}
+
+/// `pos` から続く数字の終わりの位置を返す
+fn digits_end(chars: &[char], pos: usize) -> usize {
+    chars[pos.min(chars.len())..]
+        .iter()
+        .position(|c| !c.is_ascii_digit())
+        .map_or(chars.len(), |len| pos + len)
+}
+
+/// `pos` から始まる最も長い単位の表記を探し、単位と表記の長さを返す
+fn unit_at(chars: &[char], pos: usize) -> Option<(Unit, usize)> {
+    UNIT_NAMES
+        .iter()
+        .filter_map(|(name, unit)| {
+            let len = name.chars().count();
+            let candidate = chars.get(pos..pos + len)?;
+            if !candidate.iter().copied().eq(name.chars()) {
+                return None;
+            }
+            let next_is_alphabetic = chars
+                .get(pos + len)
+                .is_some_and(|c| c.is_ascii_alphabetic());
+            if name.is_ascii() && next_is_alphabetic {
+                return None;
+            }
+            Some((*unit, len))
+        })
+        .max_by_key(|(_, len)| *len)
+}
+
+/// `int_part.frac_part` (+ 半) `unit` をミリ秒に変換する
+/// ミリ秒未満は切り捨てる
+fn component_millis(
+    int_part: &[char],
+    frac_part: &[char],
+    unit: Unit,
+    is_half: bool,
+) -> Option<u64> {
+    let int_value = int_part.iter().try_fold(0u64, |value, c| {
+        value.checked_mul(10)?.checked_add(c.to_digit(10)? as u64)
+    })?;
+    let mut millis = int_value.checked_mul(unit.millis())?;
+
+    // 小数部は 18 桁を超える分を無視する (u128 に収めるため)
+    let frac_part = &frac_part[..frac_part.len().min(18)];
+    let frac_value = frac_part
+        .iter()
+        .try_fold(0u128, |value, c| Some(value * 10 + c.to_digit(10)? as u128))?;
+    let frac_millis = frac_value * unit.millis() as u128 / 10u128.pow(frac_part.len() as u32);
+    millis = millis.checked_add(frac_millis as u64)?;
+
+    if is_half {
+        millis = millis.checked_add(unit.millis() / 2)?;
+    }
+
+    Some(millis)
+}
+
+/// like 1日5時間, 3分, 5秒
+///
+/// 最も大きい単位とその次の単位までを表示する (秒未満は切り上げ)
+pub fn format_duration(duration: Duration) -> String {
+    format_duration_with(duration, Unit::name)
+}
+
+/// like 1d5h, 3m, 5s
+///
+/// [`format_duration`] の英語表記
+pub fn format_duration_short(duration: Duration) -> String {
+    format_duration_with(duration, Unit::short_name)
+}
+
+fn format_duration_with(duration: Duration, unit_name: fn(Unit) -> &'static str) -> String {
+    let mut rest =
+        (duration.as_millis() as u64).div_ceil(Unit::Second.millis()) * Unit::Second.millis();
+    let values = [Unit::Day, Unit::Hour, Unit::Minute, Unit::Second].map(|unit| {
+        let value = rest / unit.millis();
+        rest %= unit.millis();
+        (value, unit)
+    });
+
+    let Some(first) = values.iter().position(|(value, _)| *value > 0) else {
+        return format!("0{}", unit_name(Unit::Second));
+    };
+    values[first..]
+        .iter()
+        .take(2)
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit_name(*unit)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const MAX: Duration = Duration::from_secs(60 * 60 * 24 * 365);
+
+    fn kind(input: &str, max: Duration) -> ParseDurationErrorKind {
+        parse_duration(input, max).unwrap_err().kind
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(
+            values in proptest::collection::vec(proptest::option::of(1u64..1000), 5)
+                .prop_filter("at least one unit", |values| values.iter().any(Option::is_some)),
+            message in "[a-z ]{0,10}",
+        ) {
+            let units = [Unit::Week, Unit::Day, Unit::Hour, Unit::Minute, Unit::Second];
+            let mut input = String::new();
+            let mut millis = 0;
+            for (value, unit) in values.iter().zip(units) {
+                if let Some(value) = value {
+                    input.push_str(&format!("{}{}", value, unit.short_name()));
+                    millis += value * unit.millis();
+                }
+            }
+            let rest = format!(" {}", message);
+            input.push_str(&rest);
+
+            let (duration, parsed_rest) = parse_duration(&input, Duration::MAX).unwrap();
+            prop_assert_eq!(duration, Duration::from_millis(millis));
+            prop_assert_eq!(parsed_rest, rest.as_str());
+        }
+
+        #[test]
+        fn does_not_panic(input in "\\PC{0,20}") {
+            let _ = parse_duration(&input, MAX).map_err(|e| e.to_string());
+        }
+    }
+
+    #[test]
+    fn units() {
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        for (input, duration, rest) in [
+            ("1時間半", minutes(90), ""),
+            ("1.5h", minutes(90), ""),
+            ("5 min 資料", minutes(5), " 資料"),
+            ("90sec", Duration::from_secs(90), ""),
+            ("3分", minutes(3), ""),
+            ("2日後 資料", minutes(2 * 24 * 60), " 資料"),
+            ("2 days", minutes(2 * 24 * 60), ""),
+            (
+                "1w2d3h4m5s",
+                Duration::from_secs(((9 * 24 + 3) * 60 + 4) * 60 + 5),
+                "",
+            ),
+        ] {
+            assert_eq!(
+                parse_duration(input, MAX),
+                Ok((duration, rest)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn rejected_units() {
+        assert_eq!(kind("5 mango", MAX), ParseDurationErrorKind::ExpectedUnit);
+        assert_eq!(kind("5mm", MAX), ParseDurationErrorKind::ExpectedUnit);
+        assert_eq!(
+            kind("5 m5 m", MAX),
+            ParseDurationErrorKind::DuplicatedUnit(Unit::Minute)
+        );
+        // 単位の後の空白で時間は終わるので、2つ目の m は時間として読まない
+        assert_eq!(
+            parse_duration("5 m m", MAX),
+            Ok((Duration::from_secs(5 * 60), " m"))
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(
+            kind("99999999999999999999s", MAX),
+            ParseDurationErrorKind::Overflow
+        );
+        assert_eq!(
+            kind("99999999999999999w", Duration::MAX),
+            ParseDurationErrorKind::Overflow
+        );
+    }
+
+    #[test]
+    fn zero_and_negative() {
+        assert_eq!(kind("0s", MAX), ParseDurationErrorKind::Zero);
+        assert_eq!(kind("-5m", MAX), ParseDurationErrorKind::Negative);
+    }
+
+    #[test]
+    fn unordered_unit() {
+        assert_eq!(kind("5s1m", MAX), ParseDurationErrorKind::UnorderedUnit);
+    }
+
+    #[test]
+    fn too_long() {
+        let max = Duration::from_secs(60 * 60 * 24);
+        assert_eq!(parse_duration("1d", max).unwrap().0, max);
+        assert_eq!(kind("1d1s", max), ParseDurationErrorKind::TooLong(max));
+    }
+
+    #[test]
+    fn caret_column() {
+        assert_eq!(
+            parse_duration("1h! 資料", MAX).unwrap_err().to_string(),
+            "不正な時間です: 不正な文字 `!` があります\n```\n1h!\n  ^\n```"
+        );
+        // 全角文字は 2 列として数える
+        assert_eq!(
+            parse_duration("1時間! 資料", MAX).unwrap_err().to_string(),
+            "不正な時間です: 不正な文字 `!` があります\n```\n1時間!\n     ^\n```"
+        );
+    }
+}
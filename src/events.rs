@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, sync::Mutex};
+
+use crate::Message;
+
+/// 統計で表示する上位の件数
+const TOP_COUNT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Set,
+    Fired,
+    Cancelled,
+}
+
+/// タイマーの設定・通知・取り消しの記録
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub kind: EventKind,
+    pub at: SystemTime,
+    pub timer_id: String,
+    /// 設定者の traQ ID
+    pub user_id: String,
+    /// 通知するチャンネル
+    pub channel_id: String,
+    /// 時間で指定して設定した場合の時間
+    #[serde(default)]
+    pub duration: Option<Duration>,
+}
+
+/// タイマーの記録 (`stats` コマンド用)
+///
+/// `path` の JSON Lines ファイルに追記し、`retention` より古い記録は起動時に捨てる
+#[derive(Debug)]
+pub struct EventLog {
+    path: PathBuf,
+    retention: Duration,
+    /// 古い順
+    events: Mutex<Vec<Event>>,
+}
+impl EventLog {
+    pub fn load(path: &Path, retention: Duration) -> Self {
+        let (mut events, line_count) = read_events(path);
+        retain_recent(&mut events, retention);
+
+        // 捨てた記録がある場合はファイルを書き直す
+        if events.len() < line_count {
+            if let Err(e) = std::fs::write(path, to_lines(&events)) {
+                log::error!("Failed to write {}: {:?}", path.display(), e);
+            }
+        }
+
+        Self {
+            path: path.to_path_buf(),
+            retention,
+            events: Mutex::new(events),
+        }
+    }
+
+    pub async fn record(&self, event: Event) {
+        let line = match serde_json::to_string(&event) {
+            Ok(line) => line + "\n",
+            Err(e) => {
+                log::error!("Failed to serialize event: {:?}", e);
+                return;
+            }
+        };
+        let mut events = self.events.lock().await;
+        events.push(event);
+        retain_recent(&mut events, self.retention);
+
+        if let Err(e) = append(&self.path, &line).await {
+            log::error!("Failed to write {}: {:?}", self.path.display(), e);
+        }
+    }
+
+    /// `since` 以降の記録のうち `filter` を満たすもの
+    pub async fn since(&self, since: SystemTime, filter: impl Fn(&Event) -> bool) -> Vec<Event> {
+        self.events
+            .lock()
+            .await
+            .iter()
+            .filter(|event| event.at >= since && filter(event))
+            .cloned()
+            .collect()
+    }
+}
+
+/// `path` の記録をすべて読み込む (`export-state` 用)
+pub fn load_events(path: &Path) -> Result<Vec<Event>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(parse_events(&content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(format!("Failed to read {}: {:?}", path.display(), e)),
+    }
+}
+
+/// `path` の記録を `events` で置き換える (`import-state` 用)
+pub async fn save_events(path: &Path, events: &[Event]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {:?}", dir.display(), e))?;
+    }
+    let tmp_path = path.with_extension("jsonl.tmp");
+    tokio::fs::write(&tmp_path, to_lines(events))
+        .await
+        .map_err(|e| format!("Failed to write {}: {:?}", tmp_path.display(), e))?;
+    tokio::fs::rename(&tmp_path, path)
+        .await
+        .map_err(|e| format!("Failed to rename {}: {:?}", tmp_path.display(), e))
+}
+
+/// 読み込めた記録と、ファイルの行数
+fn read_events(path: &Path) -> (Vec<Event>, usize) {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::error!("Failed to read {}: {:?}", path.display(), e);
+            }
+            String::new()
+        }
+    };
+    (parse_events(&content), content.lines().count())
+}
+
+/// 読み込めない行は飛ばす
+fn parse_events(content: &str) -> Vec<Event> {
+    content
+        .lines()
+        .filter_map(|line| {
+            serde_json::from_str::<Event>(line)
+                .map_err(|e| log::warn!("Failed to parse event {:?}: {:?}", line, e))
+                .ok()
+        })
+        .collect()
+}
+
+fn to_lines(events: &[Event]) -> String {
+    events
+        .iter()
+        .filter_map(|event| serde_json::to_string(event).ok())
+        .map(|line| line + "\n")
+        .collect()
+}
+
+fn retain_recent(events: &mut Vec<Event>, retention: Duration) {
+    if let Some(limit) = SystemTime::now().checked_sub(retention) {
+        events.retain(|event| event.at >= limit);
+    }
+}
+
+async fn append(path: &Path, line: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(line.as_bytes()).await
+}
+
+impl Event {
+    pub fn new(kind: EventKind, message: &Message) -> Self {
+        Self {
+            kind,
+            at: SystemTime::now(),
+            timer_id: message.id.clone(),
+            user_id: message.user_id.clone(),
+            channel_id: message.channel_id.clone(),
+            duration: message.duration,
+        }
+    }
+}
+
+/// `stats` コマンドで表示する集計
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub set: usize,
+    pub fired: usize,
+    pub cancelled: usize,
+    /// よく使う時間と回数
+    pub durations: Vec<(Duration, usize)>,
+    /// 設定が多い時間帯 (`timezone` での時) と回数
+    pub hours: Vec<(u32, usize)>,
+    /// 設定が多いチャンネルの id と回数
+    pub channels: Vec<(String, usize)>,
+}
+impl Stats {
+    pub fn new(events: &[Event], timezone: Tz) -> Self {
+        let count = |kind| events.iter().filter(|event| event.kind == kind).count();
+        let set_events = events
+            .iter()
+            .filter(|event| event.kind == EventKind::Set)
+            .collect::<Vec<_>>();
+        Self {
+            set: set_events.len(),
+            fired: count(EventKind::Fired),
+            cancelled: count(EventKind::Cancelled),
+            durations: top(set_events.iter().filter_map(|event| event.duration)),
+            hours: top(set_events.iter().map(|event| {
+                DateTime::<Utc>::from(event.at)
+                    .with_timezone(&timezone)
+                    .hour()
+            })),
+            channels: top(set_events.iter().map(|event| event.channel_id.clone())),
+        }
+    }
+}
+
+/// 多い順 (同じ場合は値の順) に上位 [`TOP_COUNT`] 件
+fn top<T: Ord + std::hash::Hash>(values: impl Iterator<Item = T>) -> Vec<(T, usize)> {
+    let mut counts = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(a_value, a_count), (b_value, b_count)| {
+        b_count.cmp(a_count).then(a_value.cmp(b_value))
+    });
+    counts.truncate(TOP_COUNT);
+    counts
+}
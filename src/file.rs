@@ -0,0 +1,48 @@
+use traq_ws_bot::{openapi::models::FileInfo, utils::create_client};
+
+const FILES_URL: &str = "https://q.trap.jp/api/v3/files";
+
+/// ファイルを `channel_id` のチャンネルにアップロードする
+///
+/// NOTE: openapi の `post_file` はファイル本体を送らないので、reqwest で直接送る
+pub async fn upload_file(
+    token: &str,
+    channel_id: &str,
+    file_name: &str,
+    mime: &str,
+    content: Vec<u8>,
+) -> Result<FileInfo, reqwest::Error> {
+    let file = reqwest::multipart::Part::bytes(content)
+        .file_name(file_name.to_string())
+        .mime_str(mime)?;
+    let form = reqwest::multipart::Form::new()
+        .part("file", file)
+        .text("channelId", channel_id.to_string());
+    create_client(token)
+        .post(FILES_URL)
+        .multipart(form)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+/// アップロードしたファイルを埋め込むための URL
+pub fn file_url(file_id: &str) -> String {
+    format!("https://q.trap.jp/files/{}", file_id)
+}
+
+/// ファイルの中身をダウンロードする
+///
+/// NOTE: openapi の `get_file` はファイル本体を JSON として parse しようとするので、reqwest で直接取得する
+pub async fn download_file(token: &str, file_id: &str) -> Result<Vec<u8>, reqwest::Error> {
+    let bytes = create_client(token)
+        .get(format!("{}/{}", FILES_URL, file_id))
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    Ok(bytes.to_vec())
+}
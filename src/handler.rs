@@ -1,10 +1,12 @@
 use std::{
-    sync::Arc,
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc},
     time::{Duration, SystemTime},
 };
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, Utc};
 use regex::Regex;
+use tokio::sync::mpsc::error::TrySendError;
 use traq_ws_bot::{
     events::{
         common,
@@ -12,6 +14,7 @@ use traq_ws_bot::{
     },
     openapi::{
         self,
+        apis::configuration::Configuration,
         models::{
             PostBotActionJoinRequest, PostBotActionLeaveRequest, PostMessageRequest,
             PostMessageStampRequest,
@@ -20,19 +23,322 @@ use traq_ws_bot::{
     utils::{create_configuration, is_mentioned_message},
 };
 
-use crate::{Message, Operation, Resource, TimerState};
+use crate::{
+    args::{Args, FlagSpec},
+    channel_settings::{ChannelSettingUpdate, Language},
+    config::Config,
+    date::{format_datetime, parse_date},
+    duration::{format_duration, format_duration_short, parse_duration},
+    events::{Event, EventKind, Stats},
+    file::{download_file, file_url, upload_file},
+    history::{Outcome, MAX_HISTORY_PER_USER},
+    holiday::BusinessDays,
+    ical,
+    logging::{self, Span},
+    metrics::METRICS,
+    settings::{trailing_characters_message, Confirm, DndAction, UserSettingUpdate, UserSettings},
+    Message, Operation, Resource, TimerRef, TimerState,
+};
 
 #[derive(Debug, Clone)]
 pub enum Parsed {
-    Add(String, SystemTime),
-    Remove(String),
-    /// isAll
-    List(bool),
+    Add {
+        message: String,
+        time: SystemTime,
+        /// 時間で指定された場合の時間 (None の場合は日時で指定されたので、解釈した日時を返信する)
+        duration: Option<Duration>,
+        /// `--urgent` が指定された場合は DND を無視する
+        urgent: bool,
+    },
+    Remove {
+        target: RemoveTarget,
+        /// `--yes` が指定された場合は確認せずに削除する
+        confirmed: bool,
+    },
+    List(ListOptions),
     Join,
-    Leave,
+    /// 離脱するチャンネルの id (None の場合はコマンドを実行したチャンネル)
+    Leave(Option<String>),
+    /// タイマーをすべて削除するユーザーの traQ ID
+    Purge(String),
+    Help,
+    /// None の場合は現在の設定を表示する
+    Config(Option<UserSettingUpdate>),
+    /// 最後に通知したタイマーを再設定する (None の場合は設定した時間)
+    Snooze(Option<Duration>),
+    /// None の場合は現在のチャンネルの設定を表示する
+    ChannelConfig(Option<ChannelSettingUpdate>),
+    /// 自分のタイマーを iCalendar 形式で書き出す
+    ExportIcs,
+    /// 添付された iCalendar ファイルの id
+    Import(String),
+    Stats {
+        /// `-a` が指定された場合は全体 (admin 以外は実行したチャンネル) の統計を表示する
+        is_all: bool,
+        /// 集計する期間 (None の場合は [`DEFAULT_STATS_PERIOD`])
+        period: Option<Duration>,
+    },
+    /// 終了したタイマーを表示する件数
+    History(usize),
+    /// 終了したタイマーをもう一度設定する
+    Redo {
+        target: TimerRef,
+        /// None の場合は元のタイマーと同じ時間
+        duration: Option<Duration>,
+    },
+}
+impl Parsed {
+    /// チャンネルごとのコマンドの禁止に使う名前
+    fn command_name(&self) -> &'static str {
+        match self {
+            Self::Add { .. } => "add",
+            Self::Remove { .. } => "remove",
+            Self::List(_) => "list",
+            Self::Join => "join",
+            Self::Leave(_) => "leave",
+            Self::Purge(_) => "purge",
+            Self::Help => "help",
+            Self::Config(_) => "config",
+            Self::Snooze(_) => "snooze",
+            Self::ChannelConfig(_) => "channel-config",
+            Self::ExportIcs => "export",
+            Self::Import(_) => "import",
+            Self::Stats { .. } => "stats",
+            Self::History(_) => "history",
+            Self::Redo { .. } => "redo",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoveTarget {
+    /// 指定したタイマー
+    Timers(Vec<TimerRef>),
+    /// 自分のタイマーすべて (`--all`)
+    Own {
+        /// `--channel`: 実行したチャンネルのタイマーのみ
+        channel_only: bool,
+    },
+}
+
+const REMOVE_FLAGS: [FlagSpec; 3] = [
+    FlagSpec {
+        names: &["--all", "-a"],
+        takes_value: false,
+    },
+    FlagSpec {
+        names: &["--channel", "-c"],
+        takes_value: false,
+    },
+    FlagSpec {
+        names: &["--yes", "-y"],
+        takes_value: false,
+    },
+];
+
+#[derive(Debug, Clone)]
+pub struct ListOptions {
+    /// `-a`: 全員のタイマー
+    is_all: bool,
+    /// `--channel`: 実行したチャンネルのタイマーのみ
+    channel_only: bool,
+    /// `--user`: 指定したユーザー (traQ ID) のタイマーのみ
+    user: Option<String>,
+    /// `--before`: この日時までに終了するタイマーのみ
+    before: Option<SystemTime>,
+    /// `--after`: この日時以降に終了するタイマーのみ
+    after: Option<SystemTime>,
+    /// `--grep`: メッセージに含まれる文字列 (小文字に変換済み)
+    grep: Option<String>,
+    sort: ListSort,
+    /// `--limit`: 表示する最大件数
+    limit: Option<usize>,
+    /// 1 から始まるページ番号
+    page: usize,
+    /// ページ送りのコマンドに使う、ページ番号以外の引数
+    args: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListSort {
+    /// 終了予定が早い順
+    End,
+    /// 設定した日時が早い順
+    Created,
+}
+
+const LIST_FLAGS: [FlagSpec; 8] = [
+    FlagSpec {
+        names: &["--all", "-a"],
+        takes_value: false,
+    },
+    FlagSpec {
+        names: &["--channel", "-c"],
+        takes_value: false,
+    },
+    FlagSpec {
+        names: &["--user", "-u"],
+        takes_value: true,
+    },
+    FlagSpec {
+        names: &["--before"],
+        takes_value: true,
+    },
+    FlagSpec {
+        names: &["--after"],
+        takes_value: true,
+    },
+    FlagSpec {
+        names: &["--grep", "-g"],
+        takes_value: true,
+    },
+    FlagSpec {
+        names: &["--sort"],
+        takes_value: true,
+    },
+    FlagSpec {
+        names: &["--limit", "-n"],
+        takes_value: true,
+    },
+];
+
+impl ListOptions {
+    /// like `-a --user @SSlime --before 明日 --grep "資料" --sort created --limit 5 2`
+    fn parse(
+        input: &str,
+        config: &Config,
+        user_settings: &UserSettings,
+        language: Language,
+    ) -> Result<Self, String> {
+        let args = Args::parse(input, &LIST_FLAGS, language)?;
+
+        let page = match args.positional.as_slice() {
+            [] => 1,
+            [page] => match page.parse::<usize>() {
+                Ok(0) => {
+                    return Err(language
+                        .pick(
+                            "ページは 1 以上で指定してください",
+                            "Page must be 1 or greater",
+                        )
+                        .to_string())
+                }
+                Ok(page) => page,
+                Err(_) => return Err(cannot_parse_message(page, language)),
+            },
+            _ => {
+                return Err(language
+                    .pick("ページは 1つだけ指定してください", "Specify only one page")
+                    .to_string())
+            }
+        };
+        let time_bound = |name| {
+            args.value(name)
+                .map(|input| parse_time_bound(input, config, user_settings, language))
+                .transpose()
+        };
+        let sort = match args.value("--sort") {
+            None | Some("end") => ListSort::End,
+            Some("created") => ListSort::Created,
+            Some(sort) => {
+                return Err(match language {
+                    Language::Ja => format!(
+                        "`{}` では並び替えられません (`end` か `created` を指定してください)",
+                        sort
+                    ),
+                    Language::En => {
+                        format!("Cannot sort by `{}` (must be `end` or `created`)", sort)
+                    }
+                })
+            }
+        };
+        let limit = match args.value("--limit") {
+            None => None,
+            Some(limit) => match limit.parse::<usize>() {
+                Ok(limit) if limit > 0 => Some(limit),
+                _ => {
+                    return Err(language
+                        .pick(
+                            "件数は 1 以上の数で指定してください",
+                            "Limit must be a number of 1 or greater",
+                        )
+                        .to_string())
+                }
+            },
+        };
+
+        Ok(Self {
+            is_all: args.has("--all"),
+            channel_only: args.has("--channel"),
+            user: args
+                .value("--user")
+                .map(|user| user.trim_start_matches('@').to_string()),
+            before: time_bound("--before")?,
+            after: time_bound("--after")?,
+            grep: args.value("--grep").map(|grep| grep.to_lowercase()),
+            sort,
+            limit,
+            page,
+            args: args.flags_to_string(),
+        })
+    }
+
+    /// 設定者以外の条件に一致するか
+    fn matches(&self, timer_message: &Message, channel_id: &str) -> bool {
+        (!self.channel_only || timer_message.channel_id == channel_id)
+            && self
+                .before
+                .is_none_or(|before| timer_message.time <= before)
+            && self.after.is_none_or(|after| timer_message.time >= after)
+            && self
+                .grep
+                .as_ref()
+                .is_none_or(|grep| timer_message.message.to_lowercase().contains(grep.as_str()))
+    }
+}
+
+/// 日時か、今からの時間として解釈する
+fn parse_time_bound(
+    input: &str,
+    config: &Config,
+    user_settings: &UserSettings,
+    language: Language,
+) -> Result<SystemTime, String> {
+    let now = SystemTime::now();
+    let local_now = DateTime::<Local>::from(now).with_timezone(&user_settings.timezone);
+    let not_datetime = || match language {
+        Language::Ja => format!("`{}` を日時として解釈できません", input),
+        Language::En => format!("Cannot parse `{}` as a date and time", input),
+    };
+    if let Some((datetime, rest)) = parse_date(input, local_now, &config.time_of_day, language)? {
+        if !rest.trim().is_empty() {
+            return Err(not_datetime());
+        }
+        return Ok(datetime.into());
+    }
+    let (duration, rest) =
+        parse_duration(input, config.max_timer_duration).map_err(|e| e.describe(language))?;
+    if !rest.trim().is_empty() {
+        return Err(not_datetime());
+    }
+    now.checked_add(duration)
+        .ok_or_else(|| duration_too_large_message(language))
+}
+
+/// like `abc` を解釈できません
+fn cannot_parse_message(input: &str, language: Language) -> String {
+    match language {
+        Language::Ja => format!("`{}` を解釈できません", input),
+        Language::En => format!("Cannot parse `{}`", input),
+    }
+}
+
+fn duration_too_large_message(language: Language) -> String {
+    language
+        .pick("時間が大きすぎます", "Duration is too large")
+        .to_string()
 }
 
-const DEFAULT_MESSAGE: &str = "時間になりました :blob_bongo:";
 /// NOTE: **not** equal user id
 const SELF_ID: &str = "c3967e92-e752-48e3-9b3d-1eb5b4e19341";
 const SELF_USER_ID: &str = "d352688f-a656-4444-8c5f-caa517e9ea1b";
@@ -44,16 +350,81 @@ const MENTION_REGEX: &str =
 const SPECIAL_MESSAGE_REGEX: &str =
     r#"!\{"type":"(user|channel|group)","raw":"(?P<raw>(?:[^\\"]|\\.)+)","id":"(?:[^\\"]|\\.)+"\}"#;
 
+/// like !{\"type\":\"channel\",\"raw\":\"#gps/times/SSlime\",\"id\":\"...\"}
+const CHANNEL_REGEX: &str =
+    r#"!\{"type":"channel","raw":"(?:[^\\"]|\\.)+","id":"(?P<id>[0-9a-f-]+)"\}"#;
+
+/// like !{\"type\":\"user\",\"raw\":\"@SSlime\",\"id\":\"...\"}
+const USER_REGEX: &str =
+    r#"!\{"type":"user","raw":"@(?P<name>(?:[^\\"]|\\.)+)","id":"(?:[^\\"]|\\.)+"\}"#;
+
+/// add の時間の前後どちらにもつけられるフラグ (メッセージの途中に書いたものはメッセージとして扱う)
+const URGENT_FLAG: &str = "--urgent";
+const WEEKDAYS_FLAG: &str = "--weekdays";
+const SKIP_HOLIDAYS_FLAG: &str = "--skip-holidays";
+const ADD_FLAGS: [&str; 3] = [URGENT_FLAG, WEEKDAYS_FLAG, SKIP_HOLIDAYS_FLAG];
+
 const COMMAND_NOT_FOUND_MESSAGE: &str = "コマンドが見つかりません :eyes_komatta:";
+const COMMAND_NOT_FOUND_MESSAGE_EN: &str = "Command not found :eyes_komatta:";
+const PERMISSION_DENIED_MESSAGE: &str = "このコマンドは管理者のみ実行できます :eyes_komatta:";
+const PERMISSION_DENIED_MESSAGE_EN: &str = "Only admins can run this command :eyes_komatta:";
+const COMMAND_DENIED_MESSAGE: &str = "このチャンネルではこのコマンドは使えません :eyes_komatta:";
+const COMMAND_DENIED_MESSAGE_EN: &str = "This command is disabled in this channel :eyes_komatta:";
+const TIMER_UNAVAILABLE_MESSAGE: &str =
+    "タイマーを処理できませんでした。しばらくしてからもう一度試してください :eyes_komatta:";
+const TIMER_UNAVAILABLE_MESSAGE_EN: &str =
+    "Failed to process the timer. Please try again later :eyes_komatta:";
+const TIMER_BUSY_MESSAGE: &str =
+    "処理待ちの操作が多すぎるため、タイマーを処理できませんでした。しばらくしてからもう一度試してください :eyes_komatta:";
+const TIMER_BUSY_MESSAGE_EN: &str =
+    "Too many operations are waiting, so the timer was not processed. Please try again later :eyes_komatta:";
+const SHUTTING_DOWN_MESSAGE: &str =
+    "終了処理中のため、タイマーを処理できませんでした。再起動後にもう一度試してください :eyes_komatta:";
+const SHUTTING_DOWN_MESSAGE_EN: &str =
+    "The bot is shutting down, so the timer was not processed. Please try again after it restarts :eyes_komatta:";
+
+/// like https://q.trap.jp/files/6bb86c45-65d5-458f-83c0-57116d81eca1
+const FILE_REGEX: &str = r#"https?://q\.trap\.jp/files/(?P<id>[0-9a-f-]+)"#;
+const MAX_IMPORT_FILE_SIZE: i64 = 1024 * 1024;
+/// `remove --all` などで一覧に表示するタイマーの最大の数
+const REMOVE_PREVIEW_COUNT: usize = 10;
+/// `list` の 1ページに表示するタイマーの数
+const LIST_PAGE_SIZE: usize = 20;
+/// `history` で件数を省略したときに表示する件数
+const DEFAULT_HISTORY_COUNT: usize = 10;
+/// `list`, `history` で表示するメッセージの最大の文字数
+const PREVIEW_LENGTH: usize = 30;
+/// `stats` で期間を省略したときに集計する期間
+const DEFAULT_STATS_PERIOD: Duration = Duration::from_secs(60 * 60 * 24 * 30);
+/// 繰り返しの予定から設定するタイマーの最大の数
+const MAX_IMPORT_OCCURRENCES: usize = 10;
+/// import の結果で表示するスキップした予定の最大の数
+const MAX_IMPORT_SKIPPED_LINES: usize = 20;
 
 const WAVE_ID: &str = "54e37bdc-7f8d-4fe9-aaf8-6173b97d0607";
 
 #[allow(clippy::redundant_allocation)]
 async fn message_like_handler(message: common::Message, resource: Arc<Arc<Resource>>) {
-    log::debug!("Received message: {:?}", message);
+    let span = Span {
+        message_id: message.id.clone(),
+        user: message.user.name.clone(),
+        channel_id: message.channel_id.clone(),
+        command: None,
+    };
+    logging::scope(span, handle_message(message, resource)).await;
+}
+
+#[allow(clippy::redundant_allocation)]
+async fn handle_message(message: common::Message, resource: Arc<Arc<Resource>>) {
+    log::debug!("Received message: {:?}", message.text);
+    resource.health.event_received();
     if message.user.bot {
         return;
     }
+    if resource.shutting_down.load(Ordering::SeqCst) {
+        log::info!("Ignored message during shutdown: {}", message.id);
+        return;
+    }
 
     let (content, has_mention) = if is_mentioned_message(&message, SELF_USER_ID) {
         let content = Regex::new(MENTION_REGEX)
@@ -62,9 +433,21 @@ async fn message_like_handler(message: common::Message, resource: Arc<Arc<Resour
             .to_string();
         (content, true)
     } else {
-        (message.text, false)
+        (message.text.clone(), false)
     };
-    let parsed = match parse(content, has_mention) {
+    let channel_settings = resource.channel_settings.get(&message.channel_id).await;
+    if !has_mention && channel_settings.require_mention {
+        return;
+    }
+    let language = channel_settings.language;
+    let user_settings = resource.user_settings.get(&message.user.id).await;
+    let parsed = match parse(
+        content,
+        has_mention,
+        &resource.config,
+        &user_settings,
+        language,
+    ) {
         Ok(parsed) => parsed,
         Err(Some(e)) => {
             let configuration = create_configuration(resource.token.clone());
@@ -79,6 +462,7 @@ async fn message_like_handler(message: common::Message, resource: Arc<Arc<Resour
             .await;
             if let Err(e) = res {
                 log::error!("Failed to post message: {:?}", e);
+                METRICS.api_error("post_message");
             }
             return;
         }
@@ -89,104 +473,382 @@ async fn message_like_handler(message: common::Message, resource: Arc<Arc<Resour
                     &configuration,
                     &message.channel_id,
                     Some(PostMessageRequest {
-                        content: COMMAND_NOT_FOUND_MESSAGE.to_string(),
+                        content: language
+                            .pick(COMMAND_NOT_FOUND_MESSAGE, COMMAND_NOT_FOUND_MESSAGE_EN)
+                            .to_string(),
                         embed: None,
                     }),
                 )
                 .await;
                 if let Err(e) = res {
                     log::error!("Failed to post message: {:?}", e);
+                    METRICS.api_error("post_message");
                 }
             }
             return;
         }
     };
 
+    if channel_settings.is_denied(parsed.command_name())
+        && !resource.config.is_admin(&message.user.name)
+    {
+        post_message(
+            &resource.token,
+            &message.channel_id,
+            language
+                .pick(COMMAND_DENIED_MESSAGE, COMMAND_DENIED_MESSAGE_EN)
+                .to_string(),
+        )
+        .await;
+        return;
+    }
+    METRICS.command(parsed.command_name());
+    logging::set_command(parsed.command_name());
+    log::info!("Received {} command", parsed.command_name());
+
     match parsed {
-        Parsed::Add(notify_message, time) => {
+        Parsed::Add {
+            message: notify_message,
+            time,
+            duration,
+            urgent,
+        } => {
+            if let Err(e) = check_add_quota(&resource, &message, time, language).await {
+                post_message(&resource.token, &message.channel_id, e).await;
+                return;
+            }
+
+            let channel_id = message.channel_id.clone();
+            let short_id = resource.allocate_short_id();
             let message = Message {
+                id: message.id.clone(),
                 message: notify_message,
                 time,
                 message_uuid: message.id,
                 channel_id: message.channel_id,
                 user_id: message.user.name,
+                user_uuid: message.user.id,
+                urgent,
+                duration,
+                command: Some("add".to_string()),
+                created_at: Some(SystemTime::now()),
+                short_id: Some(short_id),
             };
-            resource.tx.send(Operation::Add(message)).await.unwrap();
-        }
-        Parsed::Remove(message_uuid) => {
-            resource
-                .tx
-                .send(Operation::Remove {
-                    remove_message_uuid: message_uuid,
-                    trigger_message_uuid: message.id,
-                    trigger_user_name: message.user.name,
-                })
+            let event = Event::new(EventKind::Set, &message);
+            if !send_operation(&resource, Operation::Add(message), &channel_id, language).await {
+                return;
+            }
+            resource.events.record(event).await;
+
+            if let Some(content) =
+                confirm_message(&user_settings, language, time, duration.is_none(), short_id)
+            {
+                post_message(&resource.token, &channel_id, content).await;
+            }
+        }
+        Parsed::Snooze(duration) => {
+            let last_fired = resource
+                .last_fired
+                .lock()
                 .await
-                .unwrap();
+                .get(&message.user.id)
+                .cloned();
+            let Some(last_fired) = last_fired else {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    language
+                        .pick(
+                            "最近通知したタイマーがありません",
+                            "No timer has been notified recently",
+                        )
+                        .to_string(),
+                )
+                .await;
+                return;
+            };
+            let duration = duration.unwrap_or(user_settings.snooze);
+            let Some(time) = SystemTime::now().checked_add(duration) else {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    duration_too_large_message(language),
+                )
+                .await;
+                return;
+            };
+            if let Err(e) = check_add_quota(&resource, &message, time, language).await {
+                post_message(&resource.token, &message.channel_id, e).await;
+                return;
+            }
+
+            let channel_id = message.channel_id.clone();
+            let short_id = resource.allocate_short_id();
+            // 通知先は元のタイマーと同じチャンネルにする
+            let message = Message {
+                id: message.id.clone(),
+                time,
+                message_uuid: message.id,
+                duration: Some(duration),
+                command: Some("snooze".to_string()),
+                created_at: Some(SystemTime::now()),
+                short_id: Some(short_id),
+                ..last_fired
+            };
+            let event = Event::new(EventKind::Set, &message);
+            if !send_operation(&resource, Operation::Add(message), &channel_id, language).await {
+                return;
+            }
+            resource.events.record(event).await;
+
+            if let Some(content) = confirm_message(&user_settings, language, time, false, short_id)
+            {
+                post_message(&resource.token, &channel_id, content).await;
+            }
         }
-        Parsed::List(is_all) => {
-            let timers = resource.timers.lock().await;
-            let mut messages = timers
-                .iter()
-                .filter_map(|(uuid, state)| {
-                    if let TimerState::Idle(user_name, end_time) = state {
-                        Some((uuid, user_name, end_time))
-                    } else {
-                        None
+        Parsed::Remove { target, confirmed } => {
+            let targets = match target {
+                RemoveTarget::Timers(targets) => targets,
+                RemoveTarget::Own { channel_only } => {
+                    let mut own_timers = {
+                        let timers = resource.timers.lock().await;
+                        timers
+                            .values()
+                            .filter_map(|state| match state {
+                                TimerState::Idle(timer_message)
+                                    if timer_message.user_id == message.user.name
+                                        && (!channel_only
+                                            || timer_message.channel_id == message.channel_id) =>
+                                {
+                                    Some(timer_message.clone())
+                                }
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>()
+                    };
+                    own_timers.sort_by_key(|timer_message| timer_message.time);
+                    if own_timers.is_empty() {
+                        post_message(
+                            &resource.token,
+                            &message.channel_id,
+                            language
+                                .pick(
+                                    "削除するタイマーはありません",
+                                    "There are no timers to remove",
+                                )
+                                .to_string(),
+                        )
+                        .await;
+                        return;
                     }
-                })
-                .filter(|(_, user_name, _)| {
-                    if is_all {
-                        true
-                    } else {
-                        *user_name == &message.user.name
+                    // まとめて削除する場合は、削除するタイマーを見せて `--yes` をつけて実行し直してもらう
+                    if !confirmed {
+                        let command = if channel_only {
+                            "timer remove --channel --yes"
+                        } else {
+                            "timer remove --all --yes"
+                        };
+                        let header = match language {
+                            Language::Ja => format!(
+                                "以下の {} 件のタイマーを削除します。よろしければ `{}` を実行してください",
+                                own_timers.len(),
+                                command
+                            ),
+                            Language::En => format!(
+                                "The following {} timers will be removed. To proceed, run `{}`",
+                                own_timers.len(),
+                                command
+                            ),
+                        };
+                        let mut lines = vec![header];
+                        lines.extend(timer_lines(&own_timers, language));
+                        post_message(&resource.token, &message.channel_id, lines.join("\n")).await;
+                        return;
                     }
-                })
+                    own_timers
+                        .into_iter()
+                        .map(|timer_message| TimerRef::Id(timer_message.id))
+                        .collect()
+                }
+            };
+            let operation = Operation::Remove {
+                targets,
+                trigger_is_admin: resource.config.is_admin(&message.user.name),
+                trigger_message_uuid: message.id,
+                trigger_channel_id: message.channel_id.clone(),
+                trigger_user_name: message.user.name,
+                span: logging::current(),
+            };
+            send_operation(&resource, operation, &message.channel_id, language).await;
+        }
+        Parsed::List(options) => {
+            let is_all = options.is_all
+                || options
+                    .user
+                    .as_ref()
+                    .is_some_and(|user| *user != message.user.name);
+            let mut messages = {
+                let timers = resource.timers.lock().await;
+                timers
+                    .values()
+                    .filter_map(|state| {
+                        if let TimerState::Idle(timer_message) = state {
+                            Some(timer_message.clone())
+                        } else {
+                            None
+                        }
+                    })
+                    .filter(|timer_message| {
+                        let is_owner = match &options.user {
+                            Some(user) => timer_message.user_id == *user,
+                            None => options.is_all || timer_message.user_id == message.user.name,
+                        };
+                        is_owner && options.matches(timer_message, &message.channel_id)
+                    })
+                    .collect::<Vec<_>>()
+            };
+            messages.sort_by_key(|timer_message| timer_message.time);
+            if options.sort == ListSort::Created {
+                // 設定日時のない古いタイマーは先頭にする
+                messages.sort_by_key(|timer_message| timer_message.created_at);
+            }
+            if let Some(limit) = options.limit {
+                messages.truncate(limit);
+            }
+
+            if messages.is_empty() {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    language
+                        .pick(
+                            "現在設定されているタイマーはありません :melting_face:",
+                            "No timers are set :melting_face:",
+                        )
+                        .to_string(),
+                )
+                .await;
+                return;
+            }
+            let total = messages.len();
+            let page_count = total.div_ceil(LIST_PAGE_SIZE);
+            let page = options.page.min(page_count);
+            let messages = messages
+                .into_iter()
+                .skip((page - 1) * LIST_PAGE_SIZE)
+                .take(LIST_PAGE_SIZE)
                 .collect::<Vec<_>>();
-            messages.sort_by_key(|(_, _, end_time)| *end_time);
-            let table_label = format!("{}|終了予定|url|", if is_all { "|設定者" } else { "" });
-            let table_separator = format!("{}|---|---|", if is_all { "|---" } else { "" });
-            let tables = messages
+
+            // 実行したチャンネル以外のタイマーはチャンネルも表示する
+            let configuration = create_configuration(resource.token.clone());
+            let mut channel_links = HashMap::new();
+            for timer_message in messages.iter() {
+                if timer_message.channel_id == message.channel_id
+                    || channel_links.contains_key(&timer_message.channel_id)
+                {
+                    continue;
+                }
+                let link = channel_link(&configuration, &timer_message.channel_id).await;
+                channel_links.insert(timer_message.channel_id.clone(), link);
+            }
+            let has_channel = !channel_links.is_empty();
+
+            let mut owner_settings = HashMap::new();
+            for timer_message in messages.iter() {
+                if !owner_settings.contains_key(&timer_message.user_uuid) {
+                    let settings = resource.user_settings.get(&timer_message.user_uuid).await;
+                    owner_settings.insert(timer_message.user_uuid.clone(), settings);
+                }
+            }
+            let notes = messages
                 .iter()
-                .map(|(uuid, user_name, &end_time)| {
-                    let url = message_url(uuid, true);
-                    let time: DateTime<Local> = end_time.into();
-                    let jst_time = time.with_timezone(&chrono_tz::Asia::Tokyo);
-                    format!(
-                        "{}|{}|{}|",
-                        if is_all {
-                            format!("| :@{}: ", user_name)
-                        } else {
-                            "".to_string()
-                        },
-                        jst_time.format("%Y-%m-%d %H:%M:%S"),
-                        url
+                .map(|timer_message| {
+                    notification_note(
+                        timer_message,
+                        &owner_settings[&timer_message.user_uuid],
+                        &user_settings,
+                        language,
                     )
                 })
                 .collect::<Vec<_>>();
-            let content = if tables.is_empty() {
-                "現在設定されているタイマーはありません :melting_face:".to_owned()
-            } else {
-                format!(
-                    "{}\n{}\n{}",
-                    table_label,
-                    table_separator,
-                    tables.join("\n")
-                )
-            };
-            let configuration = create_configuration(resource.token.clone());
-            let res = openapi::apis::message_api::post_message(
-                &configuration,
-                &message.channel_id,
-                Some(PostMessageRequest {
-                    content,
-                    embed: None,
-                }),
-            )
-            .await;
-            if let Err(e) = res {
-                log::error!("Failed to post message: {:?}", e);
+            let has_note = notes.iter().any(|note| note.is_some());
+
+            let mut labels = vec!["ID".to_string()];
+            if is_all {
+                labels.push(language.pick("設定者", "User").to_string());
+            }
+            if has_channel {
+                labels.push(language.pick("チャンネル", "Channel").to_string());
+            }
+            labels.push(format!(
+                "{} ({})",
+                language.pick("終了予定", "Ends at"),
+                user_settings.timezone.name()
+            ));
+            labels.push(language.pick("残り", "Remaining").to_string());
+            labels.push(language.pick("メッセージ", "Message").to_string());
+            if has_note {
+                labels.push(language.pick("備考", "Note").to_string());
+            }
+            labels.push("url".to_string());
+
+            let now = SystemTime::now();
+            let rows = messages.iter().zip(notes).map(|(timer_message, note)| {
+                let time: DateTime<Local> = timer_message.time.into();
+                let local_time = time.with_timezone(&user_settings.timezone);
+                // 秒まで表示すると長いので、1分以上の場合は分までにする
+                let remaining = timer_message.time.duration_since(now).unwrap_or_default();
+                let remaining = if remaining >= Duration::from_secs(60) {
+                    Duration::from_secs(remaining.as_secs() / 60 * 60)
+                } else {
+                    remaining
+                };
+                let mut cells = vec![timer_message.short_id_label()];
+                if is_all {
+                    cells.push(format!(":@{}:", timer_message.user_id));
+                }
+                if has_channel {
+                    cells.push(
+                        channel_links
+                            .get(&timer_message.channel_id)
+                            .cloned()
+                            .unwrap_or_default(),
+                    );
+                }
+                cells.push(local_time.format("%Y-%m-%d %H:%M:%S").to_string());
+                cells.push(match language {
+                    Language::Ja => format!("あと {}", format_duration(remaining)),
+                    Language::En => format!("in {}", format_duration_short(remaining)),
+                });
+                cells.push(preview(&timer_message.message));
+                if has_note {
+                    cells.push(note.unwrap_or_default());
+                }
+                cells.push(message_url(&timer_message.message_uuid, true));
+                table_row(&cells)
+            });
+
+            let mut lines = vec![table_row(&labels), table_row(&vec!["---"; labels.len()])];
+            lines.extend(rows);
+            if page_count > 1 {
+                let next_page = page % page_count + 1;
+                let command = if options.args.is_empty() {
+                    format!("timer list {}", next_page)
+                } else {
+                    format!("timer list {} {}", options.args, next_page)
+                };
+                lines.push(match language {
+                    Language::Ja => format!(
+                        "\n{} / {} ページ (全 {} 件, 次のページは `{}`)",
+                        page, page_count, total, command
+                    ),
+                    Language::En => format!(
+                        "\nPage {} of {} ({} timers, next: `{}`)",
+                        page, page_count, total, command
+                    ),
+                });
             }
+            post_message(&resource.token, &message.channel_id, lines.join("\n")).await;
         }
         Parsed::Join => {
             let configuration = create_configuration(resource.token.clone());
@@ -205,24 +867,40 @@ async fn message_like_handler(message: common::Message, resource: Arc<Arc<Resour
             .await;
             if let Err(e) = res {
                 log::error!("Failed to join channel: {:?}", e);
+                METRICS.api_error("join_channel");
             }
 
             let res = openapi::apis::message_api::post_message(
                 &configuration,
                 &message.channel_id,
                 Some(PostMessageRequest {
-                    content: "参加しました :blob_pyon:".to_string(),
+                    content: language
+                        .pick("参加しました :blob_pyon:", "Joined :blob_pyon:")
+                        .to_string(),
                     embed: None,
                 }),
             )
             .await;
             if let Err(e) = res {
                 log::error!("Failed to post message: {:?}", e);
+                METRICS.api_error("post_message");
             }
         }
-        Parsed::Leave => {
+        Parsed::Leave(channel_id) => {
+            if channel_id.is_some() && !resource.config.is_admin(&message.user.name) {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    language
+                        .pick(PERMISSION_DENIED_MESSAGE, PERMISSION_DENIED_MESSAGE_EN)
+                        .to_string(),
+                )
+                .await;
+                return;
+            }
             let configuration = create_configuration(resource.token.clone());
-            let channel_id_uuid = uuid::Uuid::parse_str(&message.channel_id);
+            let channel_id = channel_id.unwrap_or(message.channel_id);
+            let channel_id_uuid = uuid::Uuid::parse_str(&channel_id);
             if let Err(e) = channel_id_uuid {
                 log::error!("Failed to parse channel id: {:?}", e);
                 return;
@@ -237,6 +915,7 @@ async fn message_like_handler(message: common::Message, resource: Arc<Arc<Resour
             .await;
             if let Err(e) = res {
                 log::error!("Failed to leave channel: {:?}", e);
+                METRICS.api_error("leave_channel");
             }
 
             let res = openapi::apis::stamp_api::add_message_stamp(
@@ -247,10 +926,1018 @@ async fn message_like_handler(message: common::Message, resource: Arc<Arc<Resour
             )
             .await;
             if let Err(e) = res {
-                log::error!("Failed to post message: {:?}", e);
+                log::error!("Failed to add stamp: {:?}", e);
+                METRICS.api_error("add_message_stamp");
+            }
+        }
+        Parsed::Purge(user_name) => {
+            if !resource.config.is_admin(&message.user.name) {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    language
+                        .pick(PERMISSION_DENIED_MESSAGE, PERMISSION_DENIED_MESSAGE_EN)
+                        .to_string(),
+                )
+                .await;
+                return;
+            }
+            let operation = Operation::Purge {
+                user_name,
+                trigger_message_uuid: message.id,
+                trigger_channel_id: message.channel_id.clone(),
+                span: logging::current(),
+            };
+            send_operation(&resource, operation, &message.channel_id, language).await;
+        }
+        Parsed::Config(update) => {
+            let content = match update {
+                Some(update) => {
+                    let settings = resource
+                        .user_settings
+                        .update(&message.user.id, update)
+                        .await;
+                    format!(
+                        "{}\n{}",
+                        language.pick("設定を変更しました", "Settings updated"),
+                        settings.describe(language)
+                    )
+                }
+                None => user_settings.describe(language),
+            };
+            post_message(&resource.token, &message.channel_id, content).await;
+        }
+        Parsed::ChannelConfig(update) => {
+            // 表示は誰でもできるが、変更はチャンネルの全員に影響するので管理者のみにする
+            if update.is_some() && !resource.config.is_admin(&message.user.name) {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    language
+                        .pick(
+                            "チャンネルの設定の変更は管理者のみ実行できます :eyes_komatta:",
+                            "Only admins can change channel settings :eyes_komatta:",
+                        )
+                        .to_string(),
+                )
+                .await;
+                return;
             }
+            let content = match update {
+                Some(update) => {
+                    let settings = resource
+                        .channel_settings
+                        .update(&message.channel_id, update)
+                        .await;
+                    format!(
+                        "{}\n{}",
+                        settings
+                            .language
+                            .pick("チャンネルの設定を変更しました", "Channel settings updated"),
+                        settings.describe(settings.language)
+                    )
+                }
+                None => channel_settings.describe(language),
+            };
+            post_message(&resource.token, &message.channel_id, content).await;
         }
+        Parsed::ExportIcs => {
+            let mut messages = {
+                let timers = resource.timers.lock().await;
+                timers
+                    .values()
+                    .filter_map(|state| match state {
+                        TimerState::Idle(timer_message)
+                            if timer_message.user_id == message.user.name =>
+                        {
+                            Some(timer_message.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+            };
+            if messages.is_empty() {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    language
+                        .pick(
+                            "現在設定されているタイマーはありません :melting_face:",
+                            "No timers are set :melting_face:",
+                        )
+                        .to_string(),
+                )
+                .await;
+                return;
+            }
+            messages.sort_by_key(|timer_message| timer_message.time);
+
+            let ics = ical::export(&messages, SystemTime::now());
+            let file_name = format!("timers-{}.ics", message.user.name);
+            let res = upload_file(
+                &resource.token,
+                &message.channel_id,
+                &file_name,
+                "text/calendar",
+                ics.clone().into_bytes(),
+            )
+            .await;
+            let content = match res {
+                Ok(file) => format!(
+                    "{}\n{}",
+                    match language {
+                        Language::Ja => format!("タイマー {} 件を書き出しました", messages.len()),
+                        Language::En => format!("Exported {} timers", messages.len()),
+                    },
+                    file_url(&file.id.to_string())
+                ),
+                // アップロードできなかった場合はメッセージに直接書く
+                Err(e) => {
+                    log::error!("Failed to upload file: {:?}", e);
+                    METRICS.api_error("post_file");
+                    format!(
+                        "```text/calendar:{}\n{}```",
+                        file_name,
+                        ics.replace("\r\n", "\n")
+                    )
+                }
+            };
+            post_message(&resource.token, &message.channel_id, content).await;
+        }
+        Parsed::Import(file_id) => {
+            if let Err(e) = acquire_add_rate_limit(&resource, &message.user.name, language).await {
+                post_message(&resource.token, &message.channel_id, e).await;
+                return;
+            }
+            let content = match download_ics(&resource.token, &file_id, language).await {
+                Ok(content) => content,
+                Err(e) => {
+                    post_message(&resource.token, &message.channel_id, e).await;
+                    return;
+                }
+            };
+            let events = ical::parse(
+                &content,
+                user_settings.timezone,
+                resource.config.time_of_day.morning,
+                language,
+            );
+
+            let config = &resource.config;
+            let now = Utc::now().with_timezone(&user_settings.timezone);
+            let until = chrono::Duration::from_std(config.max_timer_duration)
+                .ok()
+                .and_then(|duration| now.checked_add_signed(duration));
+            let (user_count, channel_count) =
+                count_timers(&resource, &message.user.name, &message.channel_id).await;
+            let mut remaining = config
+                .max_timers_per_user
+                .saturating_sub(user_count)
+                .min(config.max_timers_per_channel.saturating_sub(channel_count));
+
+            let mut timers = vec![];
+            let mut skipped = vec![];
+            for event in events {
+                let summary = if event.summary.trim().is_empty() {
+                    user_settings.default_message().to_string()
+                } else {
+                    event.summary.trim().to_string()
+                };
+                let schedule = match event.schedule {
+                    Ok(schedule) => schedule,
+                    Err(reason) => {
+                        skipped.push((summary, reason));
+                        continue;
+                    }
+                };
+                let occurrences =
+                    match schedule.occurrences(now, until, MAX_IMPORT_OCCURRENCES, language) {
+                        Ok(occurrences) => occurrences,
+                        Err(reason) => {
+                            skipped.push((summary, reason));
+                            continue;
+                        }
+                    };
+                if occurrences.is_empty() {
+                    let reason = if until.is_some_and(|until| schedule.start > until) {
+                        match language {
+                            Language::Ja => format!(
+                                "{} 日より先の予定です",
+                                config.max_timer_duration.as_secs() / (60 * 60 * 24)
+                            ),
+                            Language::En => format!(
+                                "more than {} days ahead",
+                                config.max_timer_duration.as_secs() / (60 * 60 * 24)
+                            ),
+                        }
+                    } else {
+                        language.pick("過去の予定です", "in the past").to_string()
+                    };
+                    skipped.push((summary, reason));
+                    continue;
+                }
+                let count = occurrences.len().min(remaining);
+                if count < occurrences.len() {
+                    skipped.push((
+                        summary.clone(),
+                        language
+                            .pick(
+                                "タイマーの数の上限に達しました",
+                                "reached the maximum number of timers",
+                            )
+                            .to_string(),
+                    ));
+                }
+                remaining -= count;
+                for datetime in occurrences.into_iter().take(count) {
+                    timers.push(Message {
+                        id: format!("{}:{}", message.id, timers.len()),
+                        message: summary.clone(),
+                        time: datetime.into(),
+                        message_uuid: message.id.clone(),
+                        channel_id: message.channel_id.clone(),
+                        user_id: message.user.name.clone(),
+                        user_uuid: message.user.id.clone(),
+                        urgent: false,
+                        duration: None,
+                        command: Some("import".to_string()),
+                        created_at: Some(SystemTime::now()),
+                        short_id: Some(resource.allocate_short_id()),
+                    });
+                }
+            }
+
+            // 途中で送れなくなっても、設定済みのタイマーを取り消せるように結果は必ず返信する
+            let total_count = timers.len();
+            let mut imported_count = 0;
+            for timer in timers {
+                let event = Event::new(EventKind::Set, &timer);
+                let operation = Operation::Add(timer);
+                if !send_operation(&resource, operation, &message.channel_id, language).await {
+                    break;
+                }
+                resource.events.record(event).await;
+                imported_count += 1;
+            }
+
+            let mut lines = vec![match language {
+                Language::Ja => format!("{} 件のタイマーを設定しました", imported_count),
+                Language::En => format!("Imported {} timers", imported_count),
+            }];
+            if imported_count < total_count {
+                lines.push(match language {
+                    Language::Ja => format!(
+                        "{} 件を設定した後に失敗したため、残りの {} 件は設定していません",
+                        imported_count,
+                        total_count - imported_count
+                    ),
+                    Language::En => format!(
+                        "Failed after {} timers, so the remaining {} were not imported",
+                        imported_count,
+                        total_count - imported_count
+                    ),
+                });
+            }
+            if imported_count > 0 {
+                lines.push(match language {
+                    Language::Ja => format!(
+                        "まとめて取り消す場合は `timer remove {}`",
+                        message_url(&message.id, false)
+                    ),
+                    Language::En => format!(
+                        "To cancel all of them, run `timer remove {}`",
+                        message_url(&message.id, false)
+                    ),
+                });
+            }
+            if !skipped.is_empty() {
+                lines.push(match language {
+                    Language::Ja => format!("スキップした予定 ({} 件):", skipped.len()),
+                    Language::En => format!("Skipped ({}):", skipped.len()),
+                });
+                for (summary, reason) in skipped.iter().take(MAX_IMPORT_SKIPPED_LINES) {
+                    lines.push(format!("- {}: {}", summary, reason));
+                }
+                if skipped.len() > MAX_IMPORT_SKIPPED_LINES {
+                    lines.push(match language {
+                        Language::Ja => {
+                            format!("- ほか {} 件", skipped.len() - MAX_IMPORT_SKIPPED_LINES)
+                        }
+                        Language::En => {
+                            format!("- and {} more", skipped.len() - MAX_IMPORT_SKIPPED_LINES)
+                        }
+                    });
+                }
+            }
+            post_message(&resource.token, &message.channel_id, lines.join("\n")).await;
+        }
+        Parsed::Stats { is_all, period } => {
+            // admin 以外の -a は実行したチャンネルの統計にする
+            let is_all_channel = is_all && resource.config.is_admin(&message.user.name);
+            let period = period
+                .unwrap_or(DEFAULT_STATS_PERIOD)
+                .min(resource.config.stats_retention);
+            let since = SystemTime::now()
+                .checked_sub(period)
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            let events = resource
+                .events
+                .since(since, |event| {
+                    if is_all_channel {
+                        true
+                    } else if is_all {
+                        event.channel_id == message.channel_id
+                    } else {
+                        event.user_id == message.user.name
+                    }
+                })
+                .await;
+            let stats = Stats::new(&events, user_settings.timezone);
+
+            let configuration = create_configuration(resource.token.clone());
+            let mut channels = vec![];
+            for (channel_id, count) in stats.channels.iter() {
+                channels.push((channel_link(&configuration, channel_id).await, *count));
+            }
+
+            let target = if is_all_channel {
+                language.pick("全体", "everyone").to_string()
+            } else if is_all {
+                language.pick("このチャンネル", "this channel").to_string()
+            } else {
+                format!("@{}", message.user.name)
+            };
+            let content =
+                stats_message(&stats, &channels, &target, period, &user_settings, language);
+            post_message(&resource.token, &message.channel_id, content).await;
+        }
+        Parsed::History(count) => {
+            let timers = resource.history.recent(&message.user.id, count).await;
+            let content = if timers.is_empty() {
+                language
+                    .pick(
+                        "終了したタイマーはありません",
+                        "No timers have finished yet",
+                    )
+                    .to_string()
+            } else {
+                let labels = [
+                    "ID".to_string(),
+                    language.pick("結果", "Result").to_string(),
+                    format!(
+                        "{} ({})",
+                        language.pick("日時", "Finished at"),
+                        user_settings.timezone.name()
+                    ),
+                    language.pick("メッセージ", "Message").to_string(),
+                    "url".to_string(),
+                ];
+                let rows = timers.iter().map(|timer| {
+                    let completed_at: DateTime<Local> = timer.completed_at.into();
+                    table_row(&[
+                        timer.message.short_id_label(),
+                        match timer.outcome {
+                            Outcome::Fired => language.pick("通知", "fired"),
+                            Outcome::Cancelled => language.pick("取り消し", "cancelled"),
+                            Outcome::Failed => language.pick("通知失敗", "failed"),
+                        }
+                        .to_string(),
+                        completed_at
+                            .with_timezone(&user_settings.timezone)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string(),
+                        preview(&timer.message.message),
+                        message_url(&timer.message.message_uuid, true),
+                    ])
+                });
+                let mut lines = vec![table_row(&labels), table_row(&vec!["---"; labels.len()])];
+                lines.extend(rows);
+                lines.join("\n")
+            };
+            post_message(&resource.token, &message.channel_id, content).await;
+        }
+        Parsed::Redo { target, duration } => {
+            let Some(completed) = resource.history.find(&message.user.id, &target).await else {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    language
+                        .pick(
+                            "終了したタイマーの中に指定したタイマーはありません",
+                            "No finished timer matches that",
+                        )
+                        .to_string(),
+                )
+                .await;
+                return;
+            };
+            let Some(duration) = duration.or(completed.message.duration) else {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    language
+                        .pick(
+                            "日時で指定したタイマーは `redo #42 時間` のように時間を指定してください",
+                            "The timer was set with a date, so specify a duration like `redo #42 10m`",
+                        )
+                        .to_string(),
+                )
+                .await;
+                return;
+            };
+            let Some(time) = SystemTime::now().checked_add(duration) else {
+                post_message(
+                    &resource.token,
+                    &message.channel_id,
+                    duration_too_large_message(language),
+                )
+                .await;
+                return;
+            };
+            if let Err(e) = check_add_quota(&resource, &message, time, language).await {
+                post_message(&resource.token, &message.channel_id, e).await;
+                return;
+            }
+
+            let channel_id = message.channel_id.clone();
+            let short_id = resource.allocate_short_id();
+            let message = Message {
+                id: message.id.clone(),
+                message: completed.message.message,
+                time,
+                message_uuid: message.id,
+                channel_id: message.channel_id,
+                user_id: message.user.name,
+                user_uuid: message.user.id,
+                urgent: completed.message.urgent,
+                duration: Some(duration),
+                command: Some("redo".to_string()),
+                created_at: Some(SystemTime::now()),
+                short_id: Some(short_id),
+            };
+            let event = Event::new(EventKind::Set, &message);
+            if !send_operation(&resource, Operation::Add(message), &channel_id, language).await {
+                return;
+            }
+            resource.events.record(event).await;
+
+            if let Some(content) = confirm_message(&user_settings, language, time, false, short_id)
+            {
+                post_message(&resource.token, &channel_id, content).await;
+            }
+        }
+        Parsed::Help => {
+            post_message(
+                &resource.token,
+                &message.channel_id,
+                help_message(&resource.config, language),
+            )
+            .await;
+        }
+    }
+}
+
+/// like `|a|b|c|`
+fn table_row<T: AsRef<str>>(cells: &[T]) -> String {
+    let cells = cells.iter().map(|cell| cell.as_ref()).collect::<Vec<_>>();
+    format!("|{}|", cells.join("|"))
+}
+
+/// 表に表示するためにメッセージを 1行にして短くする
+fn preview(message: &str) -> String {
+    let message = message.replace('\n', " ").replace('|', "\\|");
+    if message.chars().count() > PREVIEW_LENGTH {
+        format!(
+            "{}…",
+            message.chars().take(PREVIEW_LENGTH).collect::<String>()
+        )
+    } else {
+        message
+    }
+}
+
+/// 削除するタイマーの一覧 (多い場合は省略する)
+fn timer_lines(messages: &[Message], language: Language) -> Vec<String> {
+    let mut lines = messages
+        .iter()
+        .take(REMOVE_PREVIEW_COUNT)
+        .map(|message| {
+            format!(
+                "- {} {}",
+                message.short_id_label(),
+                preview(&message.message)
+            )
+        })
+        .collect::<Vec<_>>();
+    if messages.len() > REMOVE_PREVIEW_COUNT {
+        let rest = messages.len() - REMOVE_PREVIEW_COUNT;
+        lines.push(match language {
+            Language::Ja => format!("- ほか {} 件", rest),
+            Language::En => format!("- and {} more", rest),
+        });
+    }
+    lines
+}
+
+/// 複数のタイマーを削除したときの返信
+/// `refused` は他人のタイマーなので削除しなかったもの、`not_found` は指定されたが一致するタイマーがなかったもの
+pub fn removed_summary(
+    removed: &[Message],
+    refused: &[Message],
+    not_found: &[TimerRef],
+    language: Language,
+) -> String {
+    let mut removed = removed.to_vec();
+    removed.sort_by_key(|message| message.time);
+    let mut lines = vec![match language {
+        Language::Ja => format!("{} 件のタイマーを削除しました", removed.len()),
+        Language::En => format!("Removed {} timers", removed.len()),
+    }];
+    lines.extend(timer_lines(&removed, language));
+    if !refused.is_empty() {
+        let refused = refused
+            .iter()
+            .map(|message| format!("`{}`", message.short_id_label()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        lines.push(match language {
+            Language::Ja => format!("他の人のタイマーなので削除しなかったタイマー: {}", refused),
+            Language::En => format!("Not removed (owned by someone else): {}", refused),
+        });
     }
+    if !not_found.is_empty() {
+        let not_found = not_found
+            .iter()
+            .map(|target| format!("`{}`", target))
+            .collect::<Vec<_>>()
+            .join(", ");
+        lines.push(match language {
+            Language::Ja => format!("見つからなかったタイマー: {}", not_found),
+            Language::En => format!("Not found: {}", not_found),
+        });
+    }
+    lines.join("\n")
+}
+
+/// `stats` コマンドの返信
+/// `channels` は多いチャンネルのリンクと回数
+fn stats_message(
+    stats: &Stats,
+    channels: &[(String, usize)],
+    target: &str,
+    period: Duration,
+    user_settings: &UserSettings,
+    language: Language,
+) -> String {
+    let join = |items: Vec<String>| {
+        if items.is_empty() {
+            "-".to_string()
+        } else {
+            items.join(", ")
+        }
+    };
+    let durations = join(
+        stats
+            .durations
+            .iter()
+            .map(|(duration, count)| format!("{} ({})", format_duration_short(*duration), count))
+            .collect(),
+    );
+    let hours = join(
+        stats
+            .hours
+            .iter()
+            .map(|(hour, count)| match language {
+                Language::Ja => format!("{}時台 ({})", hour, count),
+                Language::En => format!("{}:00-{}:59 ({})", hour, hour, count),
+            })
+            .collect(),
+    );
+    let channels = join(
+        channels
+            .iter()
+            .map(|(link, count)| format!("{} ({})", link, count))
+            .collect(),
+    );
+    match language {
+        Language::Ja => format!(
+            "### {} の直近 {} の統計\n|項目|値|\n|---|---|\n|設定|{} 件|\n|通知|{} 件|\n|取り消し|{} 件|\n|よく使う時間|{}|\n|多い時間帯 ({})|{}|\n|多いチャンネル|{}|",
+            target,
+            format_duration(period),
+            stats.set,
+            stats.fired,
+            stats.cancelled,
+            durations,
+            user_settings.timezone.name(),
+            hours,
+            channels
+        ),
+        Language::En => format!(
+            "### Stats of {} for the last {}\n|Item|Value|\n|---|---|\n|Set|{}|\n|Fired|{}|\n|Cancelled|{}|\n|Common durations|{}|\n|Busiest hours ({})|{}|\n|Top channels|{}|",
+            target,
+            format_duration_short(period),
+            stats.set,
+            stats.fired,
+            stats.cancelled,
+            durations,
+            user_settings.timezone.name(),
+            hours,
+            channels
+        ),
+    }
+}
+
+/// タイマーを設定したときの返信 (設定に応じて返信しない場合は None)
+fn confirm_message(
+    user_settings: &UserSettings,
+    language: Language,
+    time: SystemTime,
+    is_absolute: bool,
+    short_id: u64,
+) -> Option<String> {
+    let local_time = DateTime::<Local>::from(time).with_timezone(&user_settings.timezone);
+    let datetime = match language {
+        Language::Ja => format_datetime(local_time),
+        Language::En => local_time.format("%Y-%m-%d (%a) %H:%M").to_string(),
+    };
+    let remaining = time
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO);
+    match (user_settings.confirm, language) {
+        (Confirm::Stamp, _) if !is_absolute => None,
+        (Confirm::Stamp | Confirm::Time, Language::Ja) => Some(format!(
+            "{} ({}) に通知します (#{})",
+            datetime,
+            local_time.format("%Z"),
+            short_id
+        )),
+        (Confirm::Stamp | Confirm::Time, Language::En) => Some(format!(
+            "Will notify at {} ({}) (#{})",
+            datetime,
+            local_time.format("%Z"),
+            short_id
+        )),
+        (Confirm::Full, Language::Ja) => Some(format!(
+            "{} ({}) に通知します (あと {})\n取り消す場合は `timer remove #{}`",
+            datetime,
+            local_time.format("%Z"),
+            format_duration(remaining),
+            short_id
+        )),
+        (Confirm::Full, Language::En) => Some(format!(
+            "Will notify at {} ({}) (in {})\nTo cancel, run `timer remove #{}`",
+            datetime,
+            local_time.format("%Z"),
+            format_duration_short(remaining),
+            short_id
+        )),
+    }
+}
+
+/// 通知時刻が設定者の DND に含まれる場合など、通知のされ方が通常と異なる場合の説明
+/// 時刻は一覧を表示するユーザーのタイムゾーンで表示する
+fn notification_note(
+    timer_message: &Message,
+    owner_settings: &UserSettings,
+    viewer_settings: &UserSettings,
+    language: Language,
+) -> Option<String> {
+    if timer_message.urgent {
+        return Some(language.pick(":bell: 緊急", ":bell: urgent").to_string());
+    }
+
+    let dnd = owner_settings.dnd?;
+    let time = DateTime::<Local>::from(timer_message.time).with_timezone(&owner_settings.timezone);
+    let end = dnd.window.end_after(time)?;
+    match dnd.action {
+        DndAction::Hold => {
+            let end = end
+                .with_timezone(&viewer_settings.timezone)
+                .format("%m-%d %H:%M");
+            Some(match language {
+                Language::Ja => format!("DND のため {} に通知", end),
+                Language::En => format!("held until {} (DND)", end),
+            })
+        }
+        DndAction::Silent => Some(
+            language
+                .pick("DND のためメンションなし", "no mention (DND)")
+                .to_string(),
+        ),
+    }
+}
+
+/// タイマーを追加できるかを、制限 (期間, 個数, 頻度) と照らし合わせて確認する
+async fn check_add_quota(
+    resource: &Resource,
+    message: &common::Message,
+    time: SystemTime,
+    language: Language,
+) -> Result<(), String> {
+    let config = &resource.config;
+
+    let duration = time
+        .duration_since(SystemTime::now())
+        .unwrap_or(Duration::ZERO);
+    if duration > config.max_timer_duration {
+        let max_days = config.max_timer_duration.as_secs() / (60 * 60 * 24);
+        return Err(match language {
+            Language::Ja => format!("タイマーは {} 日後までしか設定できません", max_days),
+            Language::En => format!("Timers can be set up to {} days ahead", max_days),
+        });
+    }
+
+    let (user_count, channel_count) =
+        count_timers(resource, &message.user.name, &message.channel_id).await;
+    if user_count >= config.max_timers_per_user {
+        return Err(match language {
+            Language::Ja => format!(
+                "1人が同時に設定できるタイマーは {} 個までです",
+                config.max_timers_per_user
+            ),
+            Language::En => format!(
+                "Each user can have up to {} timers at a time",
+                config.max_timers_per_user
+            ),
+        });
+    }
+    if channel_count >= config.max_timers_per_channel {
+        return Err(match language {
+            Language::Ja => format!(
+                "1つのチャンネルに同時に設定できるタイマーは {} 個までです",
+                config.max_timers_per_channel
+            ),
+            Language::En => format!(
+                "Each channel can have up to {} timers at a time",
+                config.max_timers_per_channel
+            ),
+        });
+    }
+
+    acquire_add_rate_limit(resource, &message.user.name, language).await
+}
+
+/// (`user_name` のタイマーの数, `channel_id` のタイマーの数) を返す
+async fn count_timers(resource: &Resource, user_name: &str, channel_id: &str) -> (usize, usize) {
+    let timers = resource.timers.lock().await;
+    timers
+        .values()
+        .fold((0, 0), |(user_count, channel_count), state| match state {
+            TimerState::Idle(timer_message) => (
+                user_count + (timer_message.user_id == user_name) as usize,
+                channel_count + (timer_message.channel_id == channel_id) as usize,
+            ),
+            TimerState::Removed => (user_count, channel_count),
+        })
+}
+
+async fn acquire_add_rate_limit(
+    resource: &Resource,
+    user_name: &str,
+    language: Language,
+) -> Result<(), String> {
+    let config = &resource.config;
+    let is_acquired = resource.add_rate_limiter.lock().await.try_acquire(
+        user_name,
+        config.add_rate_limit,
+        config.add_rate_limit_interval,
+    );
+    if !is_acquired {
+        return Err(match language {
+            Language::Ja => format!(
+                "タイマーの追加は {} 秒間に {} 回までです。しばらく待ってから再度お試しください",
+                config.add_rate_limit_interval.as_secs(),
+                config.add_rate_limit
+            ),
+            Language::En => format!(
+                "You can add up to {} timers per {} seconds. Please wait and try again",
+                config.add_rate_limit,
+                config.add_rate_limit_interval.as_secs()
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// 添付された .ics ファイルを取得する
+async fn download_ics(token: &str, file_id: &str, language: Language) -> Result<String, String> {
+    let download_failed = || {
+        language
+            .pick("ファイルを取得できませんでした", "Failed to get the file")
+            .to_string()
+    };
+    let configuration = create_configuration(token);
+    let meta = openapi::apis::file_api::get_file_meta(&configuration, file_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get file meta: {:?}", e);
+            METRICS.api_error("get_file_meta");
+            download_failed()
+        })?;
+    if !meta.name.to_ascii_lowercase().ends_with(".ics") && meta.mime != "text/calendar" {
+        return Err(match language {
+            Language::Ja => format!("{} は .ics ファイルではありません", meta.name),
+            Language::En => format!("{} is not an .ics file", meta.name),
+        });
+    }
+    if meta.size > MAX_IMPORT_FILE_SIZE {
+        return Err(match language {
+            Language::Ja => format!(
+                "{} KB より大きいファイルは読み込めません",
+                MAX_IMPORT_FILE_SIZE / 1024
+            ),
+            Language::En => format!(
+                "Files larger than {} KB cannot be imported",
+                MAX_IMPORT_FILE_SIZE / 1024
+            ),
+        });
+    }
+
+    let content = download_file(token, file_id).await.map_err(|e| {
+        log::error!("Failed to download file: {:?}", e);
+        METRICS.api_error("get_file");
+        download_failed()
+    })?;
+    String::from_utf8(content).map_err(|_| {
+        language
+            .pick("ファイルが UTF-8 ではありません", "The file is not UTF-8")
+            .to_string()
+    })
+}
+
+fn help_message(config: &Config, language: Language) -> String {
+    let max_days = config.max_timer_duration.as_secs() / (60 * 60 * 24);
+    match language {
+        Language::Ja => format!(
+            r#"### 使い方
+`timer` を冒頭につけるか、BOT にメンションしてコマンドを実行します
+- `add 1w2d3h4m5s メッセージ`: タイマーを設定する (+, add, a, set, s)
+  - 時間は `3分`, `1時間半`, `90sec`, `1.5h`, `5 min` のようにも書けます
+  - `明日の朝9時`, `来週月曜 10:00`, `月末` のように日時でも指定できます
+  - 日時で指定した場合は `--weekdays` (土日), `--skip-holidays` (祝日) をつけると次の平日にずらします
+- `remove #ID` / `remove メッセージのURL`: タイマーを削除する (-, remove, r, delete, d)
+  - 複数指定でき、`--all` (自分のタイマーすべて), `--channel` (このチャンネルの自分のタイマー) でまとめて削除できます (`--yes` で確定)
+- `list [オプション] [ページ]`: 設定されているタイマーを一覧表示する (list, ls, l)
+  - `-a` (全員), `--channel` (このチャンネル), `--user @ユーザー`, `--before 日時`, `--after 日時`, `--grep 文字列`, `--sort created|end`, `--limit 件数` で絞り込めます
+- `join` / `leave`: チャンネルに参加 / 離脱する (メンション必須)
+- `snooze [時間]`: 最後に通知したタイマーをもう一度設定する
+- `export ics`: 自分のタイマーを iCalendar (.ics) ファイルに書き出す
+- `import` + .ics ファイルを添付: ファイルの予定をタイマーとして設定する
+- `stats [-a] [期間]`: 自分 (-a で全体) のタイマーの統計を表示する (期間のデフォルトは 30日)
+- `history [件数]`: 自分の終了したタイマーを新しい順に表示する (history, log)
+- `redo #ID [時間]`: 終了したタイマーをもう一度設定する (時間を省略すると元のタイマーと同じ時間)
+- `config [設定項目 値]`: 自分の設定を表示 / 変更する (値に `reset` を指定するとデフォルトに戻す)
+  - `message メッセージ`: メッセージを省略したときの通知メッセージ
+  - `delivery channel|dm`: タイマーを設定したチャンネルと DM のどちらに通知するか
+  - `confirm stamp|time|full`: タイマーを設定したときの返信の詳しさ
+  - `tz タイムゾーン`: 日時の解釈と表示に使うタイムゾーン (Asia/Tokyo など)
+  - `snooze 時間`: `snooze` で時間を省略したときの時間
+  - `dnd 23:00-08:00 [hold|silent]`: DND の時間帯の通知を保留するかメンションなしにする (`add --urgent` で無視)
+- `channel-config [設定項目 値]`: このチャンネルの設定を表示 / 変更する (変更は管理者のみ)
+  - `deny コマンド` / `allow コマンド`: このチャンネルで使えないコマンドを追加 / 削除する
+  - `mention on|off`: BOT が参加していてもメンションしたときのみ反応するか
+  - `lang ja|en`: BOT の返信の言語
+  - `tz タイムゾーン`: 静かな時間帯の解釈に使うタイムゾーン
+  - `quiet 23:00-08:00 [defer|dm]`: 静かな時間帯の通知を遅らせるか DM にする
+- `help`: このメッセージを表示する
+
+### 制限
+- 設定できるのは {} 日後まで
+- 1人が同時に設定できるタイマーは {} 個まで
+- 1つのチャンネルに同時に設定できるタイマーは {} 個まで
+- タイマーの追加は {} 秒間に {} 回まで"#,
+            max_days,
+            config.max_timers_per_user,
+            config.max_timers_per_channel,
+            config.add_rate_limit_interval.as_secs(),
+            config.add_rate_limit,
+        ),
+        Language::En => format!(
+            r#"### Usage
+Start with `timer` or mention the bot to run a command
+- `add 1w2d3h4m5s message`: set a timer (+, add, a, set, s)
+  - durations like `90sec`, `1.5h`, `5 min` and Japanese dates like `明日の朝9時` are also accepted
+  - with a date, `--weekdays` / `--skip-holidays` shift the timer to the next business day
+- `remove #ID` / `remove message URL`: remove a timer (-, remove, r, delete, d)
+  - accepts several IDs, or `--all` (all your timers) / `--channel` (your timers in this channel) to remove in bulk (confirm with `--yes`)
+- `list [options] [page]`: list timers (list, ls, l)
+  - filter with `-a` (everyone), `--channel` (this channel), `--user @name`, `--before date`, `--after date`, `--grep text`, `--sort created|end`, `--limit n`
+- `join` / `leave`: join / leave this channel (mention required)
+- `snooze [duration]`: set the last notified timer again
+- `export ics`: export your timers as an iCalendar (.ics) file
+- `import` with an attached .ics file: set timers from the events in the file
+- `stats [-a] [period]`: show statistics of your timers (or everyone's with -a, default period is 30 days)
+- `history [count]`: show your finished timers, newest first (history, log)
+- `redo #ID [duration]`: set a finished timer again (with the same duration if omitted)
+- `config [key value]`: show / change your settings (`reset` restores the default)
+  - `message text`, `delivery channel|dm`, `confirm stamp|time|full`, `tz timezone`, `snooze duration`
+  - `dnd 23:00-08:00 [hold|silent]`: hold notifications or send them without mention during DND (ignored by `add --urgent`)
+- `channel-config [key value]`: show / change the settings of this channel (changes are admin only)
+  - `deny command` / `allow command`: disable / enable commands in this channel
+  - `mention on|off`: respond only when mentioned even if joined
+  - `lang ja|en`: reply language
+  - `tz timezone`: timezone of the quiet hours
+  - `quiet 23:00-08:00 [defer|dm]`: defer notifications or send them by DM during quiet hours
+- `help`: show this message
+
+### Limits
+- Timers can be set up to {} days ahead
+- Up to {} timers per user
+- Up to {} timers per channel
+- Up to {} timers can be added per {} seconds"#,
+            max_days,
+            config.max_timers_per_user,
+            config.max_timers_per_channel,
+            config.add_rate_limit,
+            config.add_rate_limit_interval.as_secs(),
+        ),
+    }
+}
+
+/// Timer に操作を送る
+/// キューがいっぱいで送れなかった場合は、`channel_id` にエラーを返信して false を返す
+/// 終了処理が始まった後は、Timer が受け取れないので送らずにエラーを返信する
+async fn send_operation(
+    resource: &Resource,
+    operation: Operation,
+    channel_id: &str,
+    language: Language,
+) -> bool {
+    if resource.shutting_down.load(Ordering::SeqCst) {
+        log::warn!("Dropped operation during shutdown: {:?}", operation);
+        post_message(
+            &resource.token,
+            channel_id,
+            language
+                .pick(SHUTTING_DOWN_MESSAGE, SHUTTING_DOWN_MESSAGE_EN)
+                .to_string(),
+        )
+        .await;
+        return false;
+    }
+    // receiver は Timer を作り直しても共有されていて閉じないので、`send` だとキューが
+    // いっぱいのときに待ち続けてしまう。待たずに失敗させて、混雑していることを返信する
+    let (message, message_en) = match resource.tx.try_send(operation) {
+        Ok(()) => return true,
+        Err(TrySendError::Full(operation)) => {
+            log::error!("Operation queue is full: {:?}", operation);
+            (TIMER_BUSY_MESSAGE, TIMER_BUSY_MESSAGE_EN)
+        }
+        Err(TrySendError::Closed(operation)) => {
+            log::error!("Operation queue is closed: {:?}", operation);
+            (TIMER_UNAVAILABLE_MESSAGE, TIMER_UNAVAILABLE_MESSAGE_EN)
+        }
+    };
+    post_message(
+        &resource.token,
+        channel_id,
+        language.pick(message, message_en).to_string(),
+    )
+    .await;
+    false
+}
+
+async fn post_message(token: &str, channel_id: &str, content: String) {
+    let configuration = create_configuration(token);
+    let res = openapi::apis::message_api::post_message(
+        &configuration,
+        channel_id,
+        Some(PostMessageRequest {
+            content,
+            embed: None,
+        }),
+    )
+    .await;
+    if let Err(e) = res {
+        log::error!("Failed to post message: {:?}", e);
+        METRICS.api_error("post_message");
+    }
+}
+
+/// チャンネルを traQ のチャンネルリンクとして埋め込むための文字列を返す
+/// DM など取得できないチャンネルの場合は `(DM)` を返す
+async fn channel_link(configuration: &Configuration, channel_id: &str) -> String {
+    let mut names = vec![];
+    let mut current_id = Some(channel_id.to_string());
+    while let Some(id) = current_id {
+        match openapi::apis::channel_api::get_channel(configuration, &id).await {
+            Ok(channel) => {
+                names.push(channel.name);
+                current_id = channel.parent_id.map(|id| id.to_string());
+            }
+            Err(e) => {
+                log::debug!("Failed to get channel: {:?}", e);
+                return "(DM)".to_string();
+            }
+        }
+    }
+    names.reverse();
+
+    format!(
+        r##"!{{"type":"channel","raw":"#{}","id":"{}"}}"##,
+        names.join("/"),
+        channel_id
+    )
 }
 
 #[allow(clippy::redundant_allocation)]
@@ -265,11 +1952,42 @@ pub async fn on_direct_message(payload: DirectMessageCreated, resource: Arc<Arc<
 const ADD_COMMAND: [&str; 5] = ["+", "add", "a", "set", "s"];
 const REMOVE_COMMAND: [&str; 5] = ["-", "remove", "r", "delete", "d"];
 const LIST_COMMAND: [&str; 3] = ["list", "l", "ls"];
+const PURGE_COMMAND: [&str; 1] = ["purge"];
+const HELP_COMMAND: [&str; 2] = ["help", "h"];
+const CONFIG_COMMAND: [&str; 1] = ["config"];
+const SNOOZE_COMMAND: [&str; 1] = ["snooze"];
+const CHANNEL_CONFIG_COMMAND: [&str; 1] = ["channel-config"];
+const EXPORT_COMMAND: [&str; 1] = ["export"];
+const IMPORT_COMMAND: [&str; 1] = ["import"];
+const STATS_COMMAND: [&str; 1] = ["stats"];
+const HISTORY_COMMAND: [&str; 2] = ["history", "log"];
+const REDO_COMMAND: [&str; 1] = ["redo"];
 
-/// like https://q.trap.jp/messages/6bb86c45-65d5-458f-83c0-57116d81eca1
-const MESSAGE_REGEX: &str = r#"(?:https?:)?//q\.trap\.jp/messages/(?P<uuid>[0-9a-f-]+)"#;
+/// like https://q.trap.jp/messages/6bb86c45-65d5-458f-83c0-57116d81eca1, 6bb86c45-65d5-458f-83c0-57116d81eca1, #42
+/// UUID と ID は埋め込みなどの一部と取り違えないように、空白で区切られたもののみ
+const TIMER_REF_REGEX: &str = r#"(?:https?:)?//q\.trap\.jp/messages/(?P<uuid>[0-9a-f-]+)|(?:^|\s)#(?P<short_id>[0-9]{1,18})\b|(?:^|\s)(?P<raw_uuid>[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})\b"#;
+
+const TIMER_REF_REQUIRED_MESSAGE: &str =
+    "メッセージの URL かタイマーの ID (`#42` など) を指定してください";
+const TIMER_REF_REQUIRED_MESSAGE_EN: &str = "Specify a message URL or a timer ID (like `#42`)";
+
+fn timer_ref(captures: &regex::Captures) -> TimerRef {
+    match (captures.name("short_id"), captures.name("uuid")) {
+        // 18 桁までなので u64 に収まる
+        (Some(short_id), _) => TimerRef::ShortId(short_id.as_str().parse().unwrap()),
+        (None, Some(uuid)) => TimerRef::MessageUuid(uuid.as_str().to_string()),
+        (None, None) => TimerRef::MessageUuid(captures["raw_uuid"].to_string()),
+    }
+}
 
-fn parse(content: String, is_mentioned: bool) -> Result<Parsed, Option<String>> {
+/// 日時の表現はユーザーのタイムゾーンの日時として解釈する
+fn parse(
+    content: String,
+    is_mentioned: bool,
+    config: &Config,
+    user_settings: &UserSettings,
+    language: Language,
+) -> Result<Parsed, Option<String>> {
     let content = content.trim();
     let splitted = content.split_whitespace().collect::<Vec<_>>();
 
@@ -278,7 +1996,11 @@ fn parse(content: String, is_mentioned: bool) -> Result<Parsed, Option<String>>
             return Ok(Parsed::Join);
         }
         if splitted.first() == Some(&"leave") {
-            return Ok(Parsed::Leave);
+            let channel_id = Regex::new(CHANNEL_REGEX)
+                .unwrap()
+                .captures(content)
+                .map(|captures| captures["id"].to_string());
+            return Ok(Parsed::Leave(channel_id));
         }
     }
 
@@ -300,166 +2022,431 @@ fn parse(content: String, is_mentioned: bool) -> Result<Parsed, Option<String>>
         (content, splitted)
     };
 
+    if splitted.is_empty() {
+        return Ok(Parsed::Help);
+    }
+
+    let special_message_regex = Regex::new(SPECIAL_MESSAGE_REGEX).unwrap();
     for command in ADD_COMMAND.iter() {
         if splitted[0] != *command {
             continue;
         }
 
         if splitted.len() < 2 {
-            return Err(Some("時間を指定してください".to_string()));
+            return Err(Some(
+                language
+                    .pick("時間を指定してください", "Specify the time")
+                    .to_string(),
+            ));
         }
 
-        let duration = parse_duration(splitted[1].to_string())?;
+        let input = content.trim_start_matches(command).trim();
+        let (time_input, mut flags) = take_leading_flags(input, &ADD_FLAGS);
+        let business_days = |flags: &[&str]| BusinessDays {
+            skip_weekends: flags.contains(&WEEKDAYS_FLAG),
+            skip_holidays: flags.contains(&SKIP_HOLIDAYS_FLAG),
+        };
         let now = std::time::SystemTime::now();
-        let time = now + duration;
+        let local_now = DateTime::<Local>::from(now).with_timezone(&user_settings.timezone);
+        // 時間の直後のフラグのみを取り除き、メッセージの途中のものはそのまま残す
+        let (time, message, duration) =
+            match parse_date(time_input, local_now, &config.time_of_day, language)? {
+                Some((datetime, rest)) => {
+                    let (message, trailing_flags) = take_leading_flags(rest, &ADD_FLAGS);
+                    flags.extend(trailing_flags);
+                    let datetime =
+                        business_days(&flags).shift(datetime, &config.holidays, language)?;
+                    (SystemTime::from(datetime), message, None)
+                }
+                None => {
+                    let (duration, rest) = parse_duration(time_input, config.max_timer_duration)
+                        .map_err(|e| e.describe(language))?;
+                    let (message, trailing_flags) = take_leading_flags(rest, &ADD_FLAGS);
+                    flags.extend(trailing_flags);
+                    if business_days(&flags).is_enabled() {
+                        return Err(Some(match language {
+                            Language::Ja => format!(
+                                "`{}`, `{}` は日時で指定したタイマーにのみ指定できます",
+                                WEEKDAYS_FLAG, SKIP_HOLIDAYS_FLAG
+                            ),
+                            Language::En => format!(
+                                "`{}` and `{}` can only be used with a date and time",
+                                WEEKDAYS_FLAG, SKIP_HOLIDAYS_FLAG
+                            ),
+                        }));
+                    }
+                    let time = now
+                        .checked_add(duration)
+                        .ok_or_else(|| duration_too_large_message(language))?;
+                    (time, message, Some(duration))
+                }
+            };
+        let urgent = flags.contains(&URGENT_FLAG);
 
-        let message = content
-            .trim_start_matches(command)
-            .trim()
-            .trim_start_matches(splitted[1])
-            .trim()
-            .to_string();
-        let message = Regex::new(SPECIAL_MESSAGE_REGEX)
-            .unwrap()
-            .replace_all(&message, "${raw}")
+        let message = special_message_regex
+            .replace_all(message.trim(), "${raw}")
             .to_string();
 
-        return Ok(Parsed::Add(
-            if message.is_empty() {
-                DEFAULT_MESSAGE.to_string()
+        return Ok(Parsed::Add {
+            message: if message.is_empty() {
+                user_settings.default_message().to_string()
             } else {
                 message
             },
             time,
-        ));
+            duration,
+            urgent,
+        });
     }
 
+    let timer_ref_regex = Regex::new(TIMER_REF_REGEX).unwrap();
     for command in REMOVE_COMMAND.iter() {
         if splitted[0] != *command {
             continue;
         }
 
         if splitted.len() < 2 {
-            return Err(Some("メッセージのURLを指定してください".to_string()));
+            return Err(Some(
+                language
+                    .pick(TIMER_REF_REQUIRED_MESSAGE, TIMER_REF_REQUIRED_MESSAGE_EN)
+                    .to_string(),
+            ));
         }
 
-        let content = content.trim_start_matches(command).trim().to_string();
-
-        let matches = Regex::new(MESSAGE_REGEX)
-            .unwrap()
-            .captures_iter(&content)
+        // メンションなどの埋め込みの id をタイマーの指定と取り違えないように元の文字列に戻す
+        let input =
+            special_message_regex.replace_all(content.trim_start_matches(command), "${raw}");
+        let args = Args::parse(&input, &REMOVE_FLAGS, language)?;
+        let positional = args.positional.join(" ");
+        let targets = timer_ref_regex
+            .captures_iter(&positional)
+            .map(|captures| timer_ref(&captures))
             .collect::<Vec<_>>();
-        if matches.is_empty() {
-            return Err(Some("メッセージのURLを指定してください".to_string()));
+        let is_own = args.has("--all") || args.has("--channel");
+        let target = match (is_own, targets.is_empty()) {
+            (true, true) => RemoveTarget::Own {
+                channel_only: args.has("--channel"),
+            },
+            (true, false) => {
+                return Err(Some(
+                    language
+                        .pick(
+                            "`--all`, `--channel` とタイマーは同時に指定できません",
+                            "`--all` and `--channel` cannot be combined with timers",
+                        )
+                        .to_string(),
+                ))
+            }
+            (false, true) => {
+                return Err(Some(
+                    language
+                        .pick(TIMER_REF_REQUIRED_MESSAGE, TIMER_REF_REQUIRED_MESSAGE_EN)
+                        .to_string(),
+                ))
+            }
+            (false, false) => RemoveTarget::Timers(targets),
+        };
+
+        return Ok(Parsed::Remove {
+            target,
+            confirmed: args.has("--yes"),
+        });
+    }
+
+    for command in LIST_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
         }
-        if matches.len() > 1 {
-            return Err(Some("メッセージのURLは1つだけ指定してください".to_string()));
+
+        // `--user @name` のメンションは埋め込みになっているので元の文字列に戻す
+        let input =
+            special_message_regex.replace_all(content.trim_start_matches(command), "${raw}");
+        let options = ListOptions::parse(&input, config, user_settings, language)?;
+
+        return Ok(Parsed::List(options));
+    }
+
+    let user_regex = Regex::new(USER_REGEX).unwrap();
+    for command in PURGE_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
+        }
+
+        if splitted.len() < 2 {
+            return Err(Some(
+                language
+                    .pick("ユーザーを指定してください", "Specify the user")
+                    .to_string(),
+            ));
         }
 
-        let uuid = matches[0]["uuid"].to_string();
+        let user_name = match user_regex.captures(content) {
+            Some(captures) => captures["name"].to_string(),
+            None => splitted[1].trim_start_matches('@').to_string(),
+        };
 
-        return Ok(Parsed::Remove(uuid));
+        return Ok(Parsed::Purge(user_name));
     }
 
-    for command in LIST_COMMAND.iter() {
+    for command in CONFIG_COMMAND.iter() {
         if splitted[0] != *command {
             continue;
         }
 
-        if content.contains("-a") {
-            return Ok(Parsed::List(true));
+        let Some(key) = splitted.get(1) else {
+            return Ok(Parsed::Config(None));
+        };
+        // message などの値は空白を含められるように、設定項目より後ろをすべて値とする
+        let value = content
+            .trim_start_matches(command)
+            .trim_start()
+            .trim_start_matches(key)
+            .trim();
+        if value.is_empty() {
+            return Err(Some(
+                language
+                    .pick(
+                        "`config 設定項目 値` の形式で指定してください",
+                        "Use the form `config key value`",
+                    )
+                    .to_string(),
+            ));
         }
+        let value = special_message_regex.replace_all(value, "${raw}");
 
-        return Ok(Parsed::List(false));
+        return Ok(Parsed::Config(Some(UserSettingUpdate::parse(
+            key,
+            &value,
+            config.max_timer_duration,
+            language,
+        )?)));
     }
 
-    Err(Some(COMMAND_NOT_FOUND_MESSAGE.to_string()))
-}
+    for command in CHANNEL_CONFIG_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
+        }
 
-/// like 1w2d3h4m5s
-fn parse_duration(duration: String) -> Result<Duration, String> {
-    struct DurationBuilder {
-        weeks: Option<u64>,
-        days: Option<u64>,
-        hours: Option<u64>,
-        minutes: Option<u64>,
-        seconds: Option<u64>,
+        let Some(key) = splitted.get(1) else {
+            return Ok(Parsed::ChannelConfig(None));
+        };
+        let value = content
+            .trim_start_matches(command)
+            .trim_start()
+            .trim_start_matches(key)
+            .trim();
+        if value.is_empty() {
+            return Err(Some(
+                language
+                    .pick(
+                        "`channel-config 設定項目 値` の形式で指定してください",
+                        "Use the form `channel-config key value`",
+                    )
+                    .to_string(),
+            ));
+        }
+
+        return Ok(Parsed::ChannelConfig(Some(ChannelSettingUpdate::parse(
+            key, value, language,
+        )?)));
     }
 
-    impl DurationBuilder {
-        fn new() -> Self {
-            Self {
-                weeks: None,
-                days: None,
-                hours: None,
-                minutes: None,
-                seconds: None,
-            }
+    for command in EXPORT_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
         }
 
-        fn build(self) -> Duration {
-            Duration::new(
-                self.seconds.unwrap_or(0)
-                    + self.minutes.unwrap_or(0) * 60
-                    + self.hours.unwrap_or(0) * 60 * 60
-                    + self.days.unwrap_or(0) * 60 * 60 * 24
-                    + self.weeks.unwrap_or(0) * 60 * 60 * 24 * 7,
-                0,
-            )
+        return match splitted[1..] {
+            ["ics"] => Ok(Parsed::ExportIcs),
+            _ => Err(Some(
+                language
+                    .pick(
+                        "`export ics` の形式で指定してください",
+                        "Use the form `export ics`",
+                    )
+                    .to_string(),
+            )),
+        };
+    }
+
+    let file_regex = Regex::new(FILE_REGEX).unwrap();
+    for command in IMPORT_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
         }
+
+        return match file_regex.captures(content) {
+            Some(captures) => Ok(Parsed::Import(captures["id"].to_string())),
+            None => Err(Some(
+                language
+                    .pick(
+                        "`timer import` に .ics ファイルを添付してください",
+                        "Attach an .ics file to `timer import`",
+                    )
+                    .to_string(),
+            )),
+        };
     }
 
-    let mut builder = DurationBuilder::new();
+    for command in SNOOZE_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
+        }
 
-    let duration_regex = Regex::new(r"(?P<value>\d+)(?P<unit>[wdhms])").unwrap();
-    for duration in duration_regex.captures_iter(&duration) {
-        let value = duration["value"].parse::<u64>().unwrap();
-        match &duration["unit"] {
-            "w" => {
-                if builder.weeks.is_some() {
-                    return Err("週は1つだけ指定してください".to_string());
-                }
-                builder.weeks = Some(value);
-            }
-            "d" => {
-                if builder.days.is_some() {
-                    return Err("日は1つだけ指定してください".to_string());
-                }
-                builder.days = Some(value);
-            }
-            "h" => {
-                if builder.hours.is_some() {
-                    return Err("時間は1つだけ指定してください".to_string());
-                }
-                builder.hours = Some(value);
-            }
-            "m" => {
-                if builder.minutes.is_some() {
-                    return Err("分は1つだけ指定してください".to_string());
-                }
-                builder.minutes = Some(value);
-            }
-            "s" => {
-                if builder.seconds.is_some() {
-                    return Err("秒は1つだけ指定してください".to_string());
+        let time_input = content.trim_start_matches(command).trim();
+        if time_input.is_empty() {
+            return Ok(Parsed::Snooze(None));
+        }
+        let (duration, rest) = parse_duration(time_input, config.max_timer_duration)
+            .map_err(|e| e.describe(language))?;
+        if !rest.trim().is_empty() {
+            return Err(Some(trailing_characters_message(language)));
+        }
+
+        return Ok(Parsed::Snooze(Some(duration)));
+    }
+
+    let all_flag_regex = flag_regex("-a");
+    for command in STATS_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
+        }
+
+        let (period_input, is_all) =
+            take_flag(content.trim_start_matches(command), &all_flag_regex);
+        let period_input = period_input.trim();
+        if period_input.is_empty() {
+            return Ok(Parsed::Stats {
+                is_all,
+                period: None,
+            });
+        }
+        let (period, rest) = parse_duration(period_input, config.stats_retention)
+            .map_err(|e| e.describe(language))?;
+        if !rest.trim().is_empty() {
+            return Err(Some(
+                language
+                    .pick(
+                        "期間の後に余計な文字があります",
+                        "Unexpected characters after the period",
+                    )
+                    .to_string(),
+            ));
+        }
+
+        return Ok(Parsed::Stats {
+            is_all,
+            period: Some(period),
+        });
+    }
+
+    for command in HISTORY_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
+        }
+
+        let count = match splitted.get(1) {
+            None => DEFAULT_HISTORY_COUNT,
+            Some(count) => match count.parse::<usize>() {
+                Ok(count) if (1..=MAX_HISTORY_PER_USER).contains(&count) => count,
+                _ => {
+                    return Err(Some(match language {
+                        Language::Ja => format!(
+                            "件数は 1 から {} までの数で指定してください",
+                            MAX_HISTORY_PER_USER
+                        ),
+                        Language::En => {
+                            format!("Count must be a number from 1 to {}", MAX_HISTORY_PER_USER)
+                        }
+                    }))
                 }
-                builder.seconds = Some(value);
-            }
-            _ => unreachable!(),
+            },
+        };
+
+        return Ok(Parsed::History(count));
+    }
+
+    for command in REDO_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
+        }
+
+        let content =
+            special_message_regex.replace_all(content.trim_start_matches(command), "${raw}");
+        let content = content.trim();
+        let Some(captures) = timer_ref_regex.captures(content) else {
+            return Err(Some(
+                language
+                    .pick(TIMER_REF_REQUIRED_MESSAGE, TIMER_REF_REQUIRED_MESSAGE_EN)
+                    .to_string(),
+            ));
+        };
+        let target = timer_ref(&captures);
+        let duration_input = timer_ref_regex.replace(content, "");
+        let duration_input = duration_input.trim();
+        if duration_input.is_empty() {
+            return Ok(Parsed::Redo {
+                target,
+                duration: None,
+            });
+        }
+        let (duration, rest) = parse_duration(duration_input, config.max_timer_duration)
+            .map_err(|e| e.describe(language))?;
+        if !rest.trim().is_empty() {
+            return Err(Some(trailing_characters_message(language)));
         }
+
+        return Ok(Parsed::Redo {
+            target,
+            duration: Some(duration),
+        });
+    }
+
+    for command in HELP_COMMAND.iter() {
+        if splitted[0] != *command {
+            continue;
+        }
+
+        return Ok(Parsed::Help);
     }
-    let rest = duration_regex.replace_all(&duration, "");
-    if !rest.is_empty() {
-        return Err(format!(
-            "不正な時間です: unexpected token {}",
-            rest.chars().next().unwrap()
-        ));
+
+    Err(Some(
+        language
+            .pick(COMMAND_NOT_FOUND_MESSAGE, COMMAND_NOT_FOUND_MESSAGE_EN)
+            .to_string(),
+    ))
+}
+
+/// like `--urgent`
+/// 空白で区切られている場合のみフラグとみなす
+fn flag_regex(flag: &str) -> Regex {
+    Regex::new(&format!(r"(^|\s){}(\s|$)", regex::escape(flag))).unwrap()
+}
+
+/// `input` の先頭から空白区切りで続く `flags` を取り除き、取り除いたフラグを返す
+fn take_leading_flags<'a>(input: &'a str, flags: &[&'static str]) -> (&'a str, Vec<&'static str>) {
+    let mut input = input.trim_start();
+    let mut taken = vec![];
+    while let Some(flag) = flags.iter().find(|flag| {
+        input
+            .strip_prefix(**flag)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+    }) {
+        taken.push(*flag);
+        input = input[flag.len()..].trim_start();
     }
+    (input, taken)
+}
 
-    Ok(builder.build())
+/// `input` からフラグを取り除き、フラグが含まれていたかを返す
+fn take_flag(input: &str, flag_regex: &Regex) -> (String, bool) {
+    (
+        flag_regex.replace_all(input, "$1").to_string(),
+        flag_regex.is_match(input),
+    )
 }
 
-fn message_url(message_uuid: &str, short: bool) -> String {
+pub fn message_url(message_uuid: &str, short: bool) -> String {
     format!(
         "{}//q.trap.jp/messages/{}",
         if short { "" } else { "https:" },
@@ -0,0 +1,176 @@
+use std::{
+    net::SocketAddr,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{metrics::METRICS, Resource};
+
+/// Timer がこの時間以上ループを回していない場合は止まっているとみなす
+const SCHEDULER_STALE_AFTER: Duration = Duration::from_secs(2 * 60);
+
+/// `/healthz`, `/readyz` で返す BOT の状態
+#[derive(Debug, Default)]
+pub struct Health {
+    /// WebSocket の BOT のタスクが動いているか
+    /// traq-ws-bot は切断されてもタスクの中で再接続するので、接続しているかどうかは分からない
+    bot_task_running: Mutex<bool>,
+    /// traQ から最後にイベントを受け取った時刻
+    last_event_at: Mutex<Option<SystemTime>>,
+    /// Timer が最後にループを回した時刻
+    scheduler_heartbeat_at: Mutex<Option<SystemTime>>,
+    /// 最後に通知に成功した時刻
+    last_notified_at: Mutex<Option<SystemTime>>,
+}
+impl Health {
+    pub fn set_bot_task_running(&self, running: bool) {
+        *self.bot_task_running.lock().unwrap() = running;
+    }
+
+    pub fn event_received(&self) {
+        *self.last_event_at.lock().unwrap() = Some(SystemTime::now());
+    }
+
+    pub fn scheduler_heartbeat(&self) {
+        *self.scheduler_heartbeat_at.lock().unwrap() = Some(SystemTime::now());
+    }
+
+    pub fn notified(&self) {
+        *self.last_notified_at.lock().unwrap() = Some(SystemTime::now());
+    }
+
+    fn is_scheduler_alive(&self) -> bool {
+        self.scheduler_heartbeat_at
+            .lock()
+            .unwrap()
+            .and_then(|time| time.elapsed().ok())
+            .is_some_and(|elapsed| elapsed < SCHEDULER_STALE_AFTER)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    /// WebSocket の BOT のタスクが動いているか (接続しているかどうかではない)
+    bot_task_running: bool,
+    last_event_at: Option<DateTime<Utc>>,
+    scheduler_alive: bool,
+    scheduler_heartbeat_at: Option<DateTime<Utc>>,
+    last_notified_at: Option<DateTime<Utc>>,
+    /// Timer が処理していない操作の数
+    queue_depth: usize,
+    queue_capacity: usize,
+    shutting_down: bool,
+}
+impl Report {
+    fn new(resource: &Resource) -> Self {
+        let health = &resource.health;
+        Self {
+            bot_task_running: *health.bot_task_running.lock().unwrap(),
+            last_event_at: health.last_event_at.lock().unwrap().map(Into::into),
+            scheduler_alive: health.is_scheduler_alive(),
+            scheduler_heartbeat_at: health
+                .scheduler_heartbeat_at
+                .lock()
+                .unwrap()
+                .map(Into::into),
+            last_notified_at: health.last_notified_at.lock().unwrap().map(Into::into),
+            queue_depth: resource.tx.max_capacity() - resource.tx.capacity(),
+            queue_capacity: resource.tx.max_capacity(),
+            shutting_down: resource.shutting_down.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Timer が止まっている場合は再起動が必要
+    fn is_healthy(&self) -> bool {
+        self.scheduler_alive
+    }
+
+    /// コマンドを受け付けられる状態か
+    fn is_ready(&self) -> bool {
+        self.is_healthy() && self.bot_task_running && !self.shutting_down
+    }
+}
+
+/// `addr` で `/healthz`, `/readyz`, `/metrics` に答える HTTP サーバーを動かす
+/// `/healthz`, `/readyz` は状態を JSON で返し、問題がある場合は 503 を返す
+pub async fn serve(addr: SocketAddr, resource: Arc<Resource>) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Failed to bind {}: {:?}", addr, e);
+            return;
+        }
+    };
+    log::info!("Health check server listening on {}", addr);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                log::error!("Failed to accept connection: {:?}", e);
+                continue;
+            }
+        };
+        let resource = resource.clone();
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream, &resource).await {
+                log::debug!("Failed to respond health check: {:?}", e);
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, resource: &Resource) -> std::io::Result<()> {
+    let mut buf = [0; 1024];
+    let len = stream.read(&mut buf).await?;
+    let request = String::from_utf8_lossy(&buf[..len]);
+    // like `GET /healthz HTTP/1.1`
+    let path = request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or_default();
+
+    if path == "/metrics" {
+        let body = METRICS.render(&*resource.timers.lock().await);
+        return write_response(&mut stream, "200 OK", "text/plain; version=0.0.4", &body).await;
+    }
+
+    let report = Report::new(resource);
+    let (is_ok, body) = match path {
+        "/healthz" => (report.is_healthy(), serde_json::to_string(&report)?),
+        "/readyz" => (report.is_ready(), serde_json::to_string(&report)?),
+        _ => {
+            return write_response(&mut stream, "404 Not Found", "text/plain", "Not Found").await;
+        }
+    };
+    let status = if is_ok {
+        "200 OK"
+    } else {
+        "503 Service Unavailable"
+    };
+    write_response(&mut stream, status, "application/json", &body).await
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
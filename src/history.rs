@@ -0,0 +1,93 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{store, Message, TimerRef};
+
+/// 1ユーザーあたりに残す終了したタイマーの数
+pub const MAX_HISTORY_PER_USER: usize = 50;
+
+/// タイマーがどう終了したか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Fired,
+    Cancelled,
+    /// 通知しようとしたが traQ への投稿に失敗した
+    Failed,
+}
+
+/// 終了したタイマー
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompletedTimer {
+    pub message: Message,
+    pub outcome: Outcome,
+    pub completed_at: SystemTime,
+}
+
+/// traQ の user id を key に、終了したタイマーを新しい順に持つ
+///
+/// 変更のたびに `path` の JSON ファイルに保存する
+#[derive(Debug)]
+pub struct History {
+    path: PathBuf,
+    timers: Mutex<HashMap<String, VecDeque<CompletedTimer>>>,
+}
+impl History {
+    pub fn load(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            timers: Mutex::new(store::load(path)),
+        }
+    }
+
+    pub async fn record(&self, message: Message, outcome: Outcome) {
+        let mut timers = self.timers.lock().await;
+        let user_timers = timers.entry(message.user_uuid.clone()).or_default();
+        user_timers.push_front(CompletedTimer {
+            message,
+            outcome,
+            completed_at: SystemTime::now(),
+        });
+        user_timers.truncate(MAX_HISTORY_PER_USER);
+        if let Err(e) = store::save(&self.path, &*timers).await {
+            log::error!("{}", e);
+        }
+    }
+
+    /// `user_uuid` の終了したタイマーを新しい順に最大 `count` 件
+    pub async fn recent(&self, user_uuid: &str, count: usize) -> Vec<CompletedTimer> {
+        self.timers
+            .lock()
+            .await
+            .get(user_uuid)
+            .map(|timers| timers.iter().take(count).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// `user_uuid` の終了したタイマーのうち、`target` に一致する最新のもの
+    pub async fn find(&self, user_uuid: &str, target: &TimerRef) -> Option<CompletedTimer> {
+        self.timers
+            .lock()
+            .await
+            .get(user_uuid)?
+            .iter()
+            .find(|timer| timer.message.matches(target))
+            .cloned()
+    }
+
+    pub async fn max_short_id(&self) -> Option<u64> {
+        self.timers
+            .lock()
+            .await
+            .values()
+            .flatten()
+            .filter_map(|timer| timer.message.short_id)
+            .max()
+    }
+}
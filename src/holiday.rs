@@ -0,0 +1,247 @@
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Weekday};
+use chrono_tz::Tz;
+
+use crate::channel_settings::Language;
+
+/// 同梱している祝日の一覧
+const BUNDLED_HOLIDAYS: &str = include_str!("../holidays.txt");
+
+/// 連休の最大の長さ (これ以上スキップする日が続く場合は一覧が壊れているとみなす)
+const MAX_CONSECUTIVE_SKIPPED_DAYS: usize = 31;
+
+/// 祝日の一覧
+#[derive(Debug, Clone)]
+pub struct Holidays {
+    /// 日付を key, 祝日の名前を value に持つ
+    dates: BTreeMap<NaiveDate, String>,
+}
+impl Default for Holidays {
+    fn default() -> Self {
+        Self::parse(BUNDLED_HOLIDAYS).expect("bundled holidays.txt is invalid")
+    }
+}
+impl Holidays {
+    /// `path` のファイルから読み込む
+    /// 読み込めない場合は同梱の一覧を使う
+    pub fn load(path: &Path) -> Self {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                log::error!("Failed to read {}: {:?}", path.display(), e);
+                return Self::default();
+            }
+        };
+        Self::parse(&content).unwrap_or_else(|e| {
+            log::error!("Failed to parse {}: {}", path.display(), e);
+            Self::default()
+        })
+    }
+
+    /// like 2026-01-01 元日
+    ///
+    /// 1行に 1日 `YYYY-MM-DD 名前` の形式で書く (名前は省略可, # 以降はコメント)
+    fn parse(content: &str) -> Result<Self, String> {
+        let mut dates = BTreeMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (date, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                format!(
+                    "{} 行目: `{}` は YYYY-MM-DD の形式ではありません",
+                    index + 1,
+                    date
+                )
+            })?;
+            dates.insert(date, name.trim().to_string());
+        }
+        Ok(Self { dates })
+    }
+
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.dates.contains_key(&date)
+    }
+
+    /// 一覧の最初の年の 1月1日 から最後の年の 12月31日 まで
+    fn covered_range(&self) -> Option<(NaiveDate, NaiveDate)> {
+        let (first, _) = self.dates.first_key_value()?;
+        let (last, _) = self.dates.last_key_value()?;
+        Some((
+            NaiveDate::from_ymd_opt(first.year(), 1, 1)?,
+            NaiveDate::from_ymd_opt(last.year(), 12, 31)?,
+        ))
+    }
+
+    /// 一覧の範囲外で、祝日かどうか分からない日付の場合はエラー
+    fn check_covered(&self, date: NaiveDate, language: Language) -> Result<(), String> {
+        match self.covered_range() {
+            Some((first, last)) if first <= date && date <= last => Ok(()),
+            Some((first, last)) => Err(match language {
+                Language::Ja => format!(
+                    "{} は祝日の一覧 ({} 年から {} 年まで) の範囲外です",
+                    date,
+                    first.year(),
+                    last.year()
+                ),
+                Language::En => format!(
+                    "{} is outside the holiday table ({} to {})",
+                    date,
+                    first.year(),
+                    last.year()
+                ),
+            }),
+            None => Err(language
+                .pick("祝日の一覧が空です", "The holiday table is empty")
+                .to_string()),
+        }
+    }
+}
+
+/// 土日や祝日を避けて通知するためのオプション
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BusinessDays {
+    /// `--weekdays`
+    pub skip_weekends: bool,
+    /// `--skip-holidays`
+    pub skip_holidays: bool,
+}
+impl BusinessDays {
+    pub fn is_enabled(&self) -> bool {
+        self.skip_weekends || self.skip_holidays
+    }
+
+    fn is_skipped(&self, date: NaiveDate, holidays: &Holidays) -> bool {
+        let is_weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        (self.skip_weekends && is_weekend) || (self.skip_holidays && holidays.is_holiday(date))
+    }
+
+    /// `datetime` がスキップする日の場合は、同じ時刻のまま次の営業日にずらす
+    ///
+    /// 祝日を避ける場合に、祝日の一覧の範囲外の日付を通ったときはエラー
+    pub fn shift(
+        &self,
+        datetime: DateTime<Tz>,
+        holidays: &Holidays,
+        language: Language,
+    ) -> Result<DateTime<Tz>, String> {
+        let mut date = datetime.date_naive();
+        for _ in 0..MAX_CONSECUTIVE_SKIPPED_DAYS {
+            if self.skip_holidays {
+                holidays.check_covered(date, language)?;
+            }
+            if !self.is_skipped(date, holidays) {
+                return datetime
+                    .timezone()
+                    .from_local_datetime(&date.and_time(datetime.time()))
+                    .earliest()
+                    .ok_or_else(|| match language {
+                        Language::Ja => {
+                            format!("{} {} は存在しない時刻です", date, datetime.time())
+                        }
+                        Language::En => format!("{} {} does not exist", date, datetime.time()),
+                    });
+            }
+            date = date.succ_opt().ok_or_else(|| {
+                language
+                    .pick("日付が大きすぎます", "Date is too large")
+                    .to_string()
+            })?;
+        }
+        Err(language
+            .pick(
+                "通知できる日が見つかりません",
+                "No day is available for the notification",
+            )
+            .to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::Asia::Tokyo;
+
+    use super::*;
+
+    fn datetime(year: i32, month: u32, day: u32) -> DateTime<Tz> {
+        Tokyo.with_ymd_and_hms(year, month, day, 10, 0, 0).unwrap()
+    }
+
+    fn shift(business_days: BusinessDays, datetime: DateTime<Tz>) -> Result<DateTime<Tz>, String> {
+        business_days.shift(datetime, &Holidays::default(), Language::Ja)
+    }
+
+    const WEEKDAYS: BusinessDays = BusinessDays {
+        skip_weekends: true,
+        skip_holidays: false,
+    };
+    const HOLIDAYS: BusinessDays = BusinessDays {
+        skip_weekends: false,
+        skip_holidays: true,
+    };
+    const BOTH: BusinessDays = BusinessDays {
+        skip_weekends: true,
+        skip_holidays: true,
+    };
+
+    #[test]
+    fn weekend() {
+        // 2026-10-17 は土曜
+        assert_eq!(
+            shift(WEEKDAYS, datetime(2026, 10, 17)),
+            Ok(datetime(2026, 10, 19))
+        );
+        assert_eq!(
+            shift(WEEKDAYS, datetime(2026, 10, 16)),
+            Ok(datetime(2026, 10, 16))
+        );
+        assert_eq!(
+            shift(HOLIDAYS, datetime(2026, 10, 17)),
+            Ok(datetime(2026, 10, 17))
+        );
+    }
+
+    #[test]
+    fn holiday() {
+        // 2026-11-03 (火) は文化の日
+        assert_eq!(
+            shift(HOLIDAYS, datetime(2026, 11, 3)),
+            Ok(datetime(2026, 11, 4))
+        );
+        assert_eq!(
+            shift(WEEKDAYS, datetime(2026, 11, 3)),
+            Ok(datetime(2026, 11, 3))
+        );
+    }
+
+    #[test]
+    fn consecutive_holidays() {
+        // 2026-05-02 (土) から 05-06 (水) まで休み
+        assert_eq!(shift(BOTH, datetime(2026, 5, 2)), Ok(datetime(2026, 5, 7)));
+        assert_eq!(
+            shift(HOLIDAYS, datetime(2026, 5, 3)),
+            Ok(datetime(2026, 5, 7))
+        );
+    }
+
+    #[test]
+    fn uncovered_year() {
+        assert!(shift(HOLIDAYS, datetime(2028, 1, 3)).is_err());
+        assert!(shift(BOTH, datetime(2028, 1, 3)).is_err());
+        // 一覧の最後の日が祝日で、範囲外の日に進む場合もエラー
+        let holidays = Holidays::parse("2027-12-31 大晦日").unwrap();
+        assert!(HOLIDAYS
+            .shift(datetime(2027, 12, 30), &holidays, Language::Ja)
+            .is_ok());
+        assert!(HOLIDAYS
+            .shift(datetime(2027, 12, 31), &holidays, Language::Ja)
+            .is_err());
+        assert_eq!(
+            shift(WEEKDAYS, datetime(2028, 1, 3)),
+            Ok(datetime(2028, 1, 3))
+        );
+    }
+}
@@ -0,0 +1,771 @@
+use std::time::SystemTime;
+
+use chrono::{
+    DateTime, Datelike, Days, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+use crate::{channel_settings::Language, handler::message_url, Message};
+
+/// RFC 5545 で推奨されている 1行の最大のバイト数 (改行を除く)
+const MAX_LINE_OCTETS: usize = 75;
+
+/// 繰り返しを展開するときに計算する最大の回数 (COUNT のある繰り返しで無限に計算しないため)
+const MAX_RRULE_ITERATIONS: u32 = 10000;
+
+/// タイマーを iCalendar (.ics) 形式で書き出す
+///
+/// タイマーごとに、通知時刻に開始する VEVENT と、開始時に通知する VALARM を 1つずつ作る
+pub fn export(messages: &[Message], now: SystemTime) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//BOT_STimer//timer export//JA".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    let stamp = format_utc(now);
+    for message in messages {
+        let url = message_url(&message.message_uuid, false);
+        let time = format_utc(message.time);
+        let summary = escape_text(&message.message);
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@q.trap.jp", message.id),
+            format!("DTSTAMP:{}", stamp),
+            format!("DTSTART:{}", time),
+            format!("DTEND:{}", time),
+            format!("SUMMARY:{}", summary),
+            format!(
+                "DESCRIPTION:{}",
+                escape_text(&format!("{}\n{}", message.message, url))
+            ),
+            format!("URL:{}", url),
+            "BEGIN:VALARM".to_string(),
+            "ACTION:DISPLAY".to_string(),
+            format!("DESCRIPTION:{}", summary),
+            "TRIGGER:PT0S".to_string(),
+            "END:VALARM".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines
+        .iter()
+        .map(|line| fold_line(line))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+        + "\r\n"
+}
+
+/// like 20230401T090000Z
+fn format_utc(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+/// TEXT 型の値として書けるように `\`, `;`, `,`, 改行をエスケープする
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 1行が [`MAX_LINE_OCTETS`] バイトを超えないように、空白から始まる行に折り返す
+/// マルチバイト文字の途中では折り返さない
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut line_octets = 0;
+    for c in line.chars() {
+        if line_octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            // 先頭の空白の分
+            line_octets = 1;
+        }
+        folded.push(c);
+        line_octets += c.len_utf8();
+    }
+    folded
+}
+
+/// 読み込んだ VEVENT
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub summary: String,
+    /// 対応していない形式の場合は理由
+    pub schedule: Result<Schedule, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    pub start: DateTime<Tz>,
+    pub rrule: Option<RRule>,
+}
+impl Schedule {
+    /// `after` より後、`until` 以前 (None の場合は無制限) の日時を最大 `max` 個返す
+    ///
+    /// [`MAX_RRULE_ITERATIONS`] 回計算しても終わらない場合はエラー
+    pub fn occurrences(
+        &self,
+        after: DateTime<Tz>,
+        until: Option<DateTime<Tz>>,
+        max: usize,
+        language: Language,
+    ) -> Result<Vec<DateTime<Tz>>, String> {
+        let is_until = |datetime: DateTime<Tz>| until.is_none_or(|until| datetime <= until);
+        let Some(rrule) = &self.rrule else {
+            return Ok(if after < self.start && is_until(self.start) {
+                vec![self.start]
+            } else {
+                vec![]
+            });
+        };
+
+        // COUNT がない場合は `after` より前の回を数える必要がないので、`after` の直前の回から計算する
+        let first = if rrule.count.is_none() {
+            rrule.index_before(self.start, after)
+        } else {
+            0
+        };
+        let mut occurrences = vec![];
+        let mut count = 0;
+        for index in first..first.saturating_add(MAX_RRULE_ITERATIONS) {
+            let Some(datetime) = rrule.nth(self.start, index) else {
+                // 2月30日 などの存在しない日時は数えない
+                continue;
+            };
+            if !is_until(datetime)
+                || rrule
+                    .until
+                    .is_some_and(|rrule_until| datetime > rrule_until)
+            {
+                return Ok(occurrences);
+            }
+            count += 1;
+            if rrule.count.is_some_and(|rrule_count| count > rrule_count) {
+                return Ok(occurrences);
+            }
+            if datetime > after {
+                occurrences.push(datetime);
+                if occurrences.len() >= max {
+                    return Ok(occurrences);
+                }
+            }
+        }
+        if occurrences.is_empty() {
+            return Err(language
+                .pick(
+                    "繰り返しが多すぎるため計算できません",
+                    "too many repetitions to compute",
+                )
+                .to_string());
+        }
+        Ok(occurrences)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// FREQ, INTERVAL, COUNT, UNTIL のみからなる単純な繰り返し
+#[derive(Debug, Clone)]
+pub struct RRule {
+    freq: Freq,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<DateTime<Tz>>,
+}
+impl RRule {
+    /// like FREQ=WEEKLY;INTERVAL=2;COUNT=10
+    fn parse(value: &str, timezone: Tz, language: Language) -> Result<Self, String> {
+        let mut freq = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+        for part in value.split(';') {
+            let (key, value) = part.split_once('=').unwrap_or((part, ""));
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Freq::Daily,
+                        "WEEKLY" => Freq::Weekly,
+                        "MONTHLY" => Freq::Monthly,
+                        "YEARLY" => Freq::Yearly,
+                        _ => {
+                            return Err(match language {
+                                Language::Ja => {
+                                    format!("FREQ={} の繰り返しには対応していません", value)
+                                }
+                                Language::En => format!("FREQ={} is not supported", value),
+                            })
+                        }
+                    })
+                }
+                "INTERVAL" => {
+                    interval = value
+                        .parse()
+                        .ok()
+                        .filter(|interval| *interval > 0)
+                        .ok_or_else(|| invalid_value_message("INTERVAL", value, language))?
+                }
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid_value_message("COUNT", value, language))?,
+                    )
+                }
+                // UNTIL はその日時を含むので、日付のみの場合はその日の終わりまでとする
+                "UNTIL" => {
+                    until = Some(parse_datetime(
+                        value,
+                        &[],
+                        timezone,
+                        NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
+                        language,
+                    )?)
+                }
+                // WKST は BYDAY などがない場合は結果に影響しない
+                "WKST" => {}
+                _ => {
+                    return Err(match language {
+                        Language::Ja => format!("繰り返しの {} には対応していません", key),
+                        Language::En => format!("{} in RRULE is not supported", key),
+                    })
+                }
+            }
+        }
+        Ok(Self {
+            freq: freq.ok_or_else(|| {
+                language
+                    .pick("繰り返しの FREQ がありません", "FREQ is missing in RRULE")
+                    .to_string()
+            })?,
+            interval,
+            count,
+            until,
+        })
+    }
+
+    /// 計算を始める番号 (これより前の回はすべて `after` の日付より前にある)
+    fn index_before(&self, start: DateTime<Tz>, after: DateTime<Tz>) -> u32 {
+        let start_date = start.date_naive();
+        let after_date = after.with_timezone(&start.timezone()).date_naive();
+        let steps = match self.freq {
+            Freq::Daily => (after_date - start_date).num_days(),
+            Freq::Weekly => (after_date - start_date).num_days() / 7,
+            Freq::Monthly => {
+                (after_date.year() as i64 - start_date.year() as i64) * 12
+                    + after_date.month() as i64
+                    - start_date.month() as i64
+            }
+            Freq::Yearly => after_date.year() as i64 - start_date.year() as i64,
+        };
+        (steps / self.interval as i64).clamp(0, u32::MAX as i64) as u32
+    }
+
+    /// `start` から数えて `index` 番目の日時 (存在しない日時の場合は None)
+    fn nth(&self, start: DateTime<Tz>, index: u32) -> Option<DateTime<Tz>> {
+        let step = index.checked_mul(self.interval)?;
+        let date = start.date_naive();
+        let date = match self.freq {
+            Freq::Daily => date.checked_add_days(Days::new(step as u64))?,
+            Freq::Weekly => date.checked_add_days(Days::new(step as u64 * 7))?,
+            // 31日 の翌月などは月末に丸めず、存在しない日付として扱う
+            Freq::Monthly => {
+                let month = date.with_day(1)?.checked_add_months(Months::new(step))?;
+                month.with_day(date.day())?
+            }
+            Freq::Yearly => NaiveDate::from_ymd_opt(
+                date.year().checked_add(step.try_into().ok()?)?,
+                date.month(),
+                date.day(),
+            )?,
+        };
+        start
+            .timezone()
+            .from_local_datetime(&date.and_time(start.time()))
+            .earliest()
+    }
+}
+
+/// iCalendar (.ics) から VEVENT を読み込む
+///
+/// タイムゾーンのない日時は `timezone` の日時、終日の予定は `all_day_time` に始まる予定として扱う
+pub fn parse(
+    content: &str,
+    timezone: Tz,
+    all_day_time: NaiveTime,
+    language: Language,
+) -> Vec<Event> {
+    let mut events = vec![];
+    // VALARM など VEVENT の中のコンポーネントのプロパティを無視するため
+    let mut components = vec![];
+    let mut summary = String::new();
+    let mut start = None;
+    let mut rrule = None;
+    for line in unfold_lines(content) {
+        let Some(Property {
+            name,
+            params,
+            value,
+        }) = parse_property(&line)
+        else {
+            continue;
+        };
+        match name.as_str() {
+            "BEGIN" => {
+                let component = value.to_ascii_uppercase();
+                if component == "VEVENT" {
+                    summary.clear();
+                    start = None;
+                    rrule = None;
+                }
+                components.push(component);
+                continue;
+            }
+            "END" => {
+                if components.pop().as_deref() == Some("VEVENT") {
+                    let schedule = match (start.take(), rrule.take().transpose()) {
+                        (None, _) => Err(language
+                            .pick("DTSTART がありません", "DTSTART is missing")
+                            .to_string()),
+                        (Some(Err(e)), _) | (_, Err(e)) => Err(e),
+                        (Some(Ok(start)), Ok(rrule)) => Ok(Schedule { start, rrule }),
+                    };
+                    events.push(Event {
+                        summary: std::mem::take(&mut summary),
+                        schedule,
+                    });
+                }
+                continue;
+            }
+            _ => {}
+        }
+        if components.last().map(String::as_str) != Some("VEVENT") {
+            continue;
+        }
+        match name.as_str() {
+            "SUMMARY" => summary = unescape_text(&value),
+            "DTSTART" => {
+                start = Some(parse_datetime(
+                    &value,
+                    &params,
+                    timezone,
+                    all_day_time,
+                    language,
+                ))
+            }
+            "RRULE" => rrule = Some(RRule::parse(&value, timezone, language)),
+            "RDATE" | "EXDATE" => {
+                rrule = Some(Err(match language {
+                    Language::Ja => format!("{} には対応していません", name),
+                    Language::En => format!("{} is not supported", name),
+                }));
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+/// 空白から始まる行を前の行につなげる
+fn unfold_lines(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in content.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// content line の 1行
+struct Property {
+    /// 大文字にしたプロパティ名
+    name: String,
+    /// (大文字にしたパラメータ名, 値)
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+/// like DTSTART;TZID=Asia/Tokyo:20230401T090000
+fn parse_property(line: &str) -> Option<Property> {
+    // パラメータの値は `"` で囲まれている場合は `:` や `;` を含められる
+    let mut in_quote = false;
+    let mut separator = None;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => in_quote = !in_quote,
+            ':' if !in_quote => {
+                separator = Some(index);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let (head, value) = line.split_at(separator?);
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    let params = parts
+        .filter_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            Some((
+                key.to_ascii_uppercase(),
+                value.trim_matches('"').to_string(),
+            ))
+        })
+        .collect();
+    Some(Property {
+        name,
+        params,
+        value: value[1..].to_string(),
+    })
+}
+
+/// like 20230401T090000Z, 20230401T090000 (TZID 付き or タイムゾーンなし), 20230401 (終日)
+fn parse_datetime(
+    value: &str,
+    params: &[(String, String)],
+    timezone: Tz,
+    all_day_time: NaiveTime,
+    language: Language,
+) -> Result<DateTime<Tz>, String> {
+    let error = || match language {
+        Language::Ja => format!("日時 `{}` は読み取れません", value),
+        Language::En => format!("Cannot read the date and time `{}`", value),
+    };
+    let param = |key: &str| {
+        params
+            .iter()
+            .find(|(param_key, _)| param_key == key)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let timezone = match param("TZID") {
+        Some(tzid) => tzid.parse::<Tz>().map_err(|_| match language {
+            Language::Ja => format!("タイムゾーン {} には対応していません", tzid),
+            Language::En => format!("Time zone {} is not supported", tzid),
+        })?,
+        None => timezone,
+    };
+    if let Some(value) = value.strip_suffix('Z') {
+        let datetime =
+            NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").map_err(|_| error())?;
+        return Ok(Utc.from_utc_datetime(&datetime).with_timezone(&timezone));
+    }
+    let datetime = if param("VALUE") == Some("DATE") || !value.contains('T') {
+        NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map_err(|_| error())?
+            .and_time(all_day_time)
+    } else {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").map_err(|_| error())?
+    };
+    timezone
+        .from_local_datetime(&datetime)
+        .earliest()
+        .ok_or_else(|| match language {
+            Language::Ja => format!("{} は存在しない日時です", datetime),
+            Language::En => format!("{} does not exist", datetime),
+        })
+}
+
+/// like INTERVAL=0 は不正な値です
+fn invalid_value_message(key: &str, value: &str, language: Language) -> String {
+    match language {
+        Language::Ja => format!("{}={} は不正な値です", key, value),
+        Language::En => format!("{}={} is invalid", key, value),
+    }
+}
+
+/// [`escape_text`] の逆
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono_tz::{America::New_York, Asia::Tokyo};
+
+    use super::*;
+
+    fn datetime(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Tz> {
+        Tokyo
+            .with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn morning() -> NaiveTime {
+        NaiveTime::from_hms_opt(9, 0, 0).unwrap()
+    }
+
+    /// VEVENT のプロパティの行から .ics を作って読み込む
+    fn parse_events(events: &[&[&str]]) -> Vec<Event> {
+        let mut lines = vec!["BEGIN:VCALENDAR"];
+        for properties in events {
+            lines.push("BEGIN:VEVENT");
+            lines.extend(properties.iter());
+            lines.push("END:VEVENT");
+        }
+        lines.push("END:VCALENDAR");
+        parse(&lines.join("\r\n"), Tokyo, morning(), Language::Ja)
+    }
+
+    fn schedule(properties: &[&str]) -> Result<Schedule, String> {
+        let mut events = parse_events(&[properties]);
+        assert_eq!(events.len(), 1);
+        events.remove(0).schedule
+    }
+
+    fn occurrences(properties: &[&str], after: DateTime<Tz>) -> Vec<DateTime<Tz>> {
+        schedule(properties)
+            .unwrap()
+            .occurrences(after, None, 100, Language::Ja)
+            .unwrap()
+    }
+
+    #[test]
+    fn count_and_until() {
+        let after = datetime(2024, 4, 30, 0, 0);
+        // UNTIL は 2024-05-03 09:00 (JST) で、その日時を含む
+        assert_eq!(
+            occurrences(
+                &[
+                    "DTSTART:20240501T090000",
+                    "RRULE:FREQ=DAILY;COUNT=5;UNTIL=20240503T000000Z"
+                ],
+                after,
+            ),
+            vec![
+                datetime(2024, 5, 1, 9, 0),
+                datetime(2024, 5, 2, 9, 0),
+                datetime(2024, 5, 3, 9, 0),
+            ]
+        );
+        assert_eq!(
+            occurrences(
+                &[
+                    "DTSTART:20240501T090000",
+                    "RRULE:FREQ=DAILY;COUNT=2;UNTIL=20240510T000000Z"
+                ],
+                after,
+            ),
+            vec![datetime(2024, 5, 1, 9, 0), datetime(2024, 5, 2, 9, 0)]
+        );
+        // `after` 以前の回も COUNT に数える
+        assert_eq!(
+            occurrences(
+                &["DTSTART:20240501T090000", "RRULE:FREQ=DAILY;COUNT=4"],
+                datetime(2024, 5, 2, 10, 0),
+            ),
+            vec![datetime(2024, 5, 3, 9, 0), datetime(2024, 5, 4, 9, 0)]
+        );
+    }
+
+    #[test]
+    fn occurrences_limit() {
+        let schedule =
+            schedule(&["DTSTART:20240501T090000", "RRULE:FREQ=WEEKLY;INTERVAL=2"]).unwrap();
+        let after = datetime(2024, 4, 30, 0, 0);
+        assert_eq!(
+            schedule
+                .occurrences(after, Some(datetime(2024, 6, 1, 0, 0)), 100, Language::Ja)
+                .unwrap(),
+            vec![
+                datetime(2024, 5, 1, 9, 0),
+                datetime(2024, 5, 15, 9, 0),
+                datetime(2024, 5, 29, 9, 0),
+            ]
+        );
+        assert_eq!(
+            schedule
+                .occurrences(after, None, 2, Language::Ja)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn long_running() {
+        let after = datetime(2024, 5, 15, 12, 0);
+        assert_eq!(
+            occurrences(
+                &["DTSTART:19900101T090000", "RRULE:FREQ=DAILY;INTERVAL=3"],
+                after
+            )[0],
+            // 1990-01-01 から 12555 (3 の倍数) 日後
+            datetime(2024, 5, 17, 9, 0)
+        );
+        assert_eq!(
+            occurrences(&["DTSTART:19000131T090000", "RRULE:FREQ=MONTHLY"], after)[..2],
+            [datetime(2024, 5, 31, 9, 0), datetime(2024, 7, 31, 9, 0)]
+        );
+        assert_eq!(
+            occurrences(&["DTSTART:19040229T090000", "RRULE:FREQ=YEARLY"], after)[0],
+            datetime(2028, 2, 29, 9, 0)
+        );
+
+        // COUNT がある場合は前の回から数える必要があるので、計算しきれない場合はエラー
+        let schedule =
+            schedule(&["DTSTART:19900101T090000", "RRULE:FREQ=DAILY;COUNT=100000"]).unwrap();
+        assert!(schedule
+            .occurrences(after, None, 100, Language::Ja)
+            .is_err());
+    }
+
+    #[test]
+    fn monthly_on_31st() {
+        // 31日 がない月は飛ばし、COUNT にも数えない
+        assert_eq!(
+            occurrences(
+                &["DTSTART:20240131T090000", "RRULE:FREQ=MONTHLY;COUNT=4"],
+                datetime(2024, 1, 1, 0, 0),
+            ),
+            vec![
+                datetime(2024, 1, 31, 9, 0),
+                datetime(2024, 3, 31, 9, 0),
+                datetime(2024, 5, 31, 9, 0),
+                datetime(2024, 7, 31, 9, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn all_day() {
+        let start = datetime(2024, 6, 1, 9, 0);
+        assert_eq!(
+            schedule(&["DTSTART;VALUE=DATE:20240601"]).unwrap().start,
+            start
+        );
+        assert_eq!(schedule(&["DTSTART:20240601"]).unwrap().start, start);
+
+        // 日付のみの UNTIL はその日の予定を含む
+        assert_eq!(
+            occurrences(
+                &[
+                    "DTSTART;VALUE=DATE:20240601",
+                    "RRULE:FREQ=DAILY;UNTIL=20240603"
+                ],
+                datetime(2024, 5, 31, 0, 0),
+            ),
+            vec![
+                start,
+                datetime(2024, 6, 2, 9, 0),
+                datetime(2024, 6, 3, 9, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn timezones() {
+        let start = schedule(&["DTSTART;TZID=America/New_York:20240601T090000"])
+            .unwrap()
+            .start;
+        assert_eq!(start.timezone(), New_York);
+        assert_eq!(start, datetime(2024, 6, 1, 22, 0));
+        // パラメータの値は `"` で囲まれていてもよい
+        assert_eq!(
+            schedule(&["DTSTART;TZID=\"America/New_York\":20240601T090000"])
+                .unwrap()
+                .start,
+            start
+        );
+
+        assert_eq!(
+            schedule(&["DTSTART:20240601T000000Z"]).unwrap().start,
+            datetime(2024, 6, 1, 9, 0)
+        );
+        assert_eq!(
+            schedule(&["DTSTART:20240601T090000"]).unwrap().start,
+            datetime(2024, 6, 1, 9, 0)
+        );
+        assert!(schedule(&["DTSTART;TZID=Mars/Olympus:20240601T090000"]).is_err());
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(schedule(&["SUMMARY:no start"]).is_err());
+        assert!(schedule(&["DTSTART:20240601T090000", "RRULE:FREQ=HOURLY"]).is_err());
+        assert!(schedule(&["DTSTART:20240601T090000", "RRULE:FREQ=DAILY;BYDAY=MO"]).is_err());
+        assert!(schedule(&["DTSTART:20240601T090000", "EXDATE:20240602T090000"]).is_err());
+    }
+
+    #[test]
+    fn unfold() {
+        assert_eq!(
+            unfold_lines("SUMMARY:abc\r\n def\r\n\tghi\r\nDTSTART:20240601"),
+            vec!["SUMMARY:abcdefghi", "DTSTART:20240601"]
+        );
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let message = |id: &str, text: &str, time: SystemTime| Message {
+            id: id.to_string(),
+            message: text.to_string(),
+            time,
+            message_uuid: "6bb86c45-65d5-458f-83c0-57116d81eca1".to_string(),
+            channel_id: "channel".to_string(),
+            user_id: "SSlime".to_string(),
+            user_uuid: "user".to_string(),
+            urgent: false,
+            duration: None,
+            command: None,
+            created_at: None,
+            short_id: None,
+        };
+        let time = SystemTime::from(datetime(2024, 6, 1, 9, 0));
+        let messages = [
+            message("1", "資料を提出する", time),
+            message(
+                "2",
+                "長いメッセージ; カンマ, バックスラッシュ \\ と\n改行を含み、1行の最大のバイト数を超えるので折り返される",
+                time + Duration::from_secs(60 * 60),
+            ),
+        ];
+
+        let ics = export(&messages, time);
+        assert!(ics.lines().all(|line| line.len() <= MAX_LINE_OCTETS));
+
+        let events = parse(&ics, New_York, morning(), Language::Ja);
+        assert_eq!(events.len(), messages.len());
+        for (event, message) in events.iter().zip(messages.iter()) {
+            assert_eq!(event.summary, message.message);
+            let schedule = event.schedule.as_ref().unwrap();
+            assert_eq!(SystemTime::from(schedule.start), message.time);
+            assert!(schedule.rrule.is_none());
+        }
+    }
+}
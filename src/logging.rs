@@ -0,0 +1,80 @@
+use std::{cell::RefCell, future::Future, io::Write};
+
+use serde::{Deserialize, Serialize};
+
+tokio::task_local! {
+    /// 処理中のコマンドの情報 (ログに付け加える)
+    static CURRENT_SPAN: RefCell<Span>;
+}
+
+/// コマンドを実行したメッセージの情報
+/// コマンドから設定したタイマーの操作や通知のログにも付け加えて、まとめて検索できるようにする
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Span {
+    pub message_id: String,
+    /// traQ ID
+    pub user: String,
+    pub channel_id: String,
+    pub command: Option<String>,
+}
+
+/// `span` を付け加えてログを出しながら `future` を実行する
+pub async fn scope<F: Future>(span: Span, future: F) -> F::Output {
+    CURRENT_SPAN.scope(RefCell::new(span), future).await
+}
+
+/// 実行中の span を返す (span の外では空の span を返す)
+pub fn current() -> Span {
+    CURRENT_SPAN
+        .try_with(|span| span.borrow().clone())
+        .unwrap_or_default()
+}
+
+/// 実行中の span に parse したコマンドを記録する
+pub fn set_command(command: &'static str) {
+    CURRENT_SPAN
+        .try_with(|span| span.borrow_mut().command = Some(command.to_string()))
+        .ok();
+}
+
+/// env_logger を初期化する
+/// 環境変数 `LOG_FORMAT` が `json` の場合は 1行に 1つの JSON で出力する
+pub fn init() {
+    let is_json = std::env::var("LOG_FORMAT").is_ok_and(|format| format == "json");
+    env_logger::Builder::from_default_env()
+        .format(move |buf, record| {
+            let span = CURRENT_SPAN.try_with(|span| span.borrow().clone()).ok();
+            if is_json {
+                let line = serde_json::json!({
+                    "timestamp": buf.timestamp_micros().to_string(),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                    "span": span,
+                });
+                writeln!(buf, "{}", line)
+            } else {
+                write!(
+                    buf,
+                    "[{} {:<5} {}] {}",
+                    buf.timestamp(),
+                    record.level(),
+                    record.target(),
+                    record.args()
+                )?;
+                if let Some(span) = span {
+                    write!(
+                        buf,
+                        " (message_id={} user={} channel_id={} command={})",
+                        span.message_id,
+                        span.user,
+                        span.channel_id,
+                        span.command.as_deref().unwrap_or("-")
+                    )?;
+                }
+                writeln!(buf)
+            }
+        })
+        .init();
+}
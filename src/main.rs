@@ -1,39 +1,143 @@
+mod args;
+mod channel_settings;
+mod config;
+mod date;
+mod duration;
+mod events;
+mod file;
 mod handler;
+mod health;
+mod history;
+mod holiday;
+mod ical;
+mod logging;
+mod metrics;
+mod quota;
+mod settings;
+mod state;
+mod store;
+mod supervisor;
 mod timer;
 
-use std::{collections::HashMap, sync::Arc, time::SystemTime};
+use std::{
+    collections::HashMap,
+    fmt,
+    io::Read,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
 
+use channel_settings::ChannelSettingsStore;
+use config::Config;
+use events::EventLog;
+use health::Health;
+use history::History;
+use logging::Span;
+use quota::UserRateLimiter;
+use serde::{Deserialize, Serialize};
+use settings::UserSettingsStore;
+use state::State;
 use timer::Timer;
-use tokio::sync::{mpsc, Mutex};
-use traq_ws_bot::builder;
+use tokio::sync::{mpsc, watch, Mutex};
 
 type Timers = Arc<Mutex<HashMap<String, TimerState>>>;
+/// traQ の user id を key に、最後に通知したタイマーを value に持つ (snooze 用)
+type LastFired = Arc<Mutex<HashMap<String, Message>>>;
 
 #[derive(Debug, Clone)]
 pub struct Resource {
     token: String,
     tx: mpsc::Sender<Operation>,
     timers: Timers,
+    config: Arc<Config>,
+    add_rate_limiter: Arc<Mutex<UserRateLimiter>>,
+    user_settings: Arc<UserSettingsStore>,
+    channel_settings: Arc<ChannelSettingsStore>,
+    last_fired: LastFired,
+    /// true の場合は終了処理中なので新しいコマンドを受け付けない
+    shutting_down: Arc<AtomicBool>,
+    health: Arc<Health>,
+    events: Arc<EventLog>,
+    history: Arc<History>,
+    /// 次に設定するタイマーの短い ID
+    next_short_id: Arc<AtomicU64>,
+}
+impl Resource {
+    fn allocate_short_id(&self) -> u64 {
+        self.next_short_id.fetch_add(1, Ordering::Relaxed)
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+// Removed は削除したタイマーを通知するまでの間だけ残るので、サイズの差は気にしない
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TimerState {
-    /// (user_name, end_time)
-    Idle(String, SystemTime),
+    Idle(Message),
     Removed,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
+    /// timer_states の key
+    /// 通常は `message_uuid` と同じで、import で設定したタイマーは `{message_uuid}:{番号}`
+    id: String,
     message: String,
     time: SystemTime,
+    /// タイマーを設定したメッセージの id (スタンプやリンクに使う)
     message_uuid: String,
     channel_id: String,
+    /// traQ ID (@ の後ろの部分)
     user_id: String,
+    /// traQ の user id
+    user_uuid: String,
+    /// true の場合は DND を無視して通知する
+    #[serde(default)]
+    urgent: bool,
+    /// 時間で指定して設定した場合の時間 (redo 用)
+    #[serde(default)]
+    duration: Option<Duration>,
+    /// タイマーを設定したコマンドの名前 (通知のログに使う)
+    #[serde(default)]
+    command: Option<String>,
+    /// タイマーを設定した日時 (list の並び替えに使う)
+    #[serde(default)]
+    created_at: Option<SystemTime>,
+    /// `#42` のようにタイマーを指定するための ID (古いタイマーは起動時に振る)
+    #[serde(default)]
+    short_id: Option<u64>,
+}
+impl Message {
+    fn matches(&self, target: &TimerRef) -> bool {
+        match target {
+            TimerRef::MessageUuid(uuid) => self.id == *uuid || self.message_uuid == *uuid,
+            TimerRef::ShortId(short_id) => self.short_id == Some(*short_id),
+            TimerRef::Id(id) => self.id == *id,
+        }
+    }
+
+    /// like `#42`
+    fn short_id_label(&self) -> String {
+        self.short_id
+            .map(|short_id| format!("#{}", short_id))
+            .unwrap_or_default()
+    }
+
+    /// タイマーを設定したコマンドの span
+    fn span(&self) -> Span {
+        Span {
+            message_id: self.message_uuid.clone(),
+            user: self.user_id.clone(),
+            channel_id: self.channel_id.clone(),
+            command: self.command.clone(),
+        }
+    }
 }
 impl PartialEq for Message {
     fn eq(&self, other: &Self) -> bool {
-        self.time == other.time && self.message_uuid == other.message_uuid
+        self.time == other.time && self.id == other.id
     }
 }
 impl PartialOrd for Message {
@@ -48,44 +152,209 @@ impl Ord for Message {
     }
 }
 
+/// コマンドでのタイマーの指定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimerRef {
+    /// タイマーを設定したメッセージの id (import で設定したタイマーはまとめて指定される)
+    MessageUuid(String),
+    /// `#42` のような短い ID
+    ShortId(u64),
+    /// timer_states の key (`remove --all` などで選んだタイマー)
+    Id(String),
+}
+impl fmt::Display for TimerRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MessageUuid(uuid) => write!(f, "{}", handler::message_url(uuid, true)),
+            Self::ShortId(short_id) => write!(f, "#{}", short_id),
+            Self::Id(id) => write!(f, "{}", id),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Operation {
     Add(Message),
     Remove {
-        remove_message_uuid: String,
+        /// 複数指定された場合は、一致するタイマーをすべて削除する
+        targets: Vec<TimerRef>,
         trigger_message_uuid: String,
+        /// 複数のタイマーを削除した場合に結果を返信するチャンネル
+        trigger_channel_id: String,
         trigger_user_name: String,
+        /// true の場合は設定者でなくても削除できる
+        trigger_is_admin: bool,
+        span: Span,
     },
+    /// 指定したユーザーのタイマーをすべて削除する (admin 用)
+    Purge {
+        user_name: String,
+        trigger_message_uuid: String,
+        trigger_channel_id: String,
+        span: Span,
+    },
+}
+impl Operation {
+    /// 操作を送ったコマンドの span
+    fn span(&self) -> Span {
+        match self {
+            Self::Add(message) => message.span(),
+            Self::Remove { span, .. } | Self::Purge { span, .. } => span.clone(),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
-    env_logger::init();
+    logging::init();
+
+    let config = Arc::new(Config::from_env());
+
+    // 別のホストに移すときなどに、DATA_DIR の内容を JSON でまとめて書き出す / 読み込む
+    match std::env::args().nth(1).as_deref() {
+        Some("export-state") => {
+            let state = match State::read(&config.data_dir) {
+                Ok(state) => state,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            match serde_json::to_string_pretty(&state) {
+                Ok(content) => println!("{}", content),
+                Err(e) => {
+                    eprintln!("Failed to serialize state: {:?}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some("import-state") => {
+            let mut content = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut content) {
+                eprintln!("Failed to read stdin: {:?}", e);
+                std::process::exit(1);
+            }
+            let result = match State::parse(&content) {
+                Ok(state) => state.save(&config.data_dir).await.map(|_| state),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(state) => eprintln!(
+                    "Imported {} timers, {} user settings, {} channel settings and {} events",
+                    state.timers.len(),
+                    state.user_settings.len(),
+                    state.channel_settings.len(),
+                    state.events.len()
+                ),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(command) => {
+            eprintln!(
+                "Unknown command `{}` (available: export-state, import-state)",
+                command
+            );
+            std::process::exit(2);
+        }
+        None => {}
+    }
 
     let token = std::env::var("BOT_ACCESS_TOKEN").expect("BOT_ACCESS_TOKEN is not set");
 
     let (tx, rx) = mpsc::channel(400);
 
-    let timers = Arc::new(Mutex::new(HashMap::new()));
-
-    let bot = builder(&token)
-        .insert_resource(Arc::new(Resource {
-            tx: tx.clone(),
-            token: token.clone(),
-            timers: timers.clone(),
-        }))
-        .on_message_created_with_resource(handler::on_message)
-        .on_direct_message_created_with_resource(handler::on_direct_message)
-        .build();
+    let timers_path = config.data_dir.join(state::TIMERS_FILE);
+    let mut timers = store::load::<Vec<Message>>(&timers_path);
+    let history = Arc::new(History::load(&config.data_dir.join(state::HISTORY_FILE)));
+    // 履歴の ID とも重ならないように、残っている ID の最大の次から振る
+    let mut next_short_id = timers
+        .iter()
+        .filter_map(|message| message.short_id)
+        .chain(history.max_short_id().await)
+        .max()
+        .map_or(1, |short_id| short_id + 1);
+    for message in timers
+        .iter_mut()
+        .filter(|message| message.short_id.is_none())
+    {
+        message.short_id = Some(next_short_id);
+        next_short_id += 1;
+    }
+    let timers = Arc::new(Mutex::new(
+        timers
+            .into_iter()
+            .map(|message| (message.id.clone(), TimerState::Idle(message)))
+            .collect::<HashMap<_, _>>(),
+    ));
+    let user_settings = Arc::new(UserSettingsStore::load(
+        &config.data_dir.join(state::USER_SETTINGS_FILE),
+    ));
+    let channel_settings = Arc::new(ChannelSettingsStore::load(
+        &config.data_dir.join(state::CHANNEL_SETTINGS_FILE),
+    ));
+    let last_fired = Arc::new(Mutex::new(HashMap::new()));
+    let events = Arc::new(EventLog::load(
+        &config.data_dir.join(state::EVENTS_FILE),
+        config.stats_retention,
+    ));
+    let shutting_down = Arc::new(AtomicBool::new(false));
+    let shutdown_timeout = config.shutdown_timeout;
 
-    let bot_process = bot.start();
+    let resource = Arc::new(Resource {
+        tx,
+        token: token.clone(),
+        timers: timers.clone(),
+        add_rate_limiter: Arc::new(Mutex::new(UserRateLimiter::default())),
+        user_settings: user_settings.clone(),
+        channel_settings: channel_settings.clone(),
+        last_fired: last_fired.clone(),
+        shutting_down: shutting_down.clone(),
+        health: Arc::new(Health::default()),
+        events,
+        history,
+        next_short_id: Arc::new(AtomicU64::new(next_short_id)),
+        config,
+    });
+    let bot_process = supervisor::supervise_bot(token.clone(), resource.clone());
+    if let Some(addr) = resource.config.health_addr {
+        tokio::spawn(health::serve(addr, resource.clone()));
+    }
 
-    let mut timer = Timer::new(token.clone(), rx, timers);
-    let timer_process = timer.run();
+    // Timer を作り直しても同じ receiver を使えるように共有する
+    let rx = Arc::new(Mutex::new(rx));
+    let new_timer = || Timer::new(&resource, rx.clone(), timers_path.clone());
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let timer_process = supervisor::supervise_timer(new_timer, shutdown_rx);
+    tokio::pin!(timer_process);
 
     tokio::select! {
         _ = bot_process => {}
-        _ = timer_process => {}
+        _ = &mut timer_process => {}
+        _ = shutdown_signal() => {
+            // 新しいコマンドを受け付けないようにしてから、Timer に残りの操作を処理させる
+            // 処理中の通知は途中で止めずに最後まで実行する
+            log::info!("Shutting down (timeout: {:?})", shutdown_timeout);
+            shutting_down.store(true, Ordering::SeqCst);
+            shutdown_tx.send(true).ok();
+            if tokio::time::timeout(shutdown_timeout, timer_process).await.is_err() {
+                log::warn!("Shutdown timed out after {:?}", shutdown_timeout);
+            }
+        }
+    }
+}
+
+/// SIGTERM か Ctrl-C (SIGINT) を受け取るまで待つ
+async fn shutdown_signal() {
+    let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("Failed to listen SIGTERM");
+    tokio::select! {
+        _ = sigterm.recv() => log::info!("Received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => log::info!("Received SIGINT"),
     }
 }
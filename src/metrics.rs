@@ -0,0 +1,183 @@
+use std::{collections::BTreeMap, collections::HashMap, fmt::Write, sync::Mutex, time::Duration};
+
+use crate::TimerState;
+
+/// 通知の遅れ (秒) のヒストグラムのバケット
+const LATENCY_BUCKETS: [f64; 10] = [0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 3600.0];
+
+/// `/metrics` で Prometheus の形式で返す値
+pub static METRICS: Metrics = Metrics::new();
+
+#[derive(Debug)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+#[derive(Debug)]
+struct Counters {
+    /// コマンド名を key に持つ実行回数
+    commands: BTreeMap<&'static str, u64>,
+    timers_added: u64,
+    timers_removed: u64,
+    timers_fired: u64,
+    /// 各バケット以下の遅れで通知した回数 (累積ではない)
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_count: u64,
+    latency_sum: f64,
+    /// traQ API の名前を key に持つ失敗回数
+    api_errors: BTreeMap<&'static str, u64>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            counters: Mutex::new(Counters {
+                commands: BTreeMap::new(),
+                timers_added: 0,
+                timers_removed: 0,
+                timers_fired: 0,
+                latency_buckets: [0; LATENCY_BUCKETS.len()],
+                latency_count: 0,
+                latency_sum: 0.0,
+                api_errors: BTreeMap::new(),
+            }),
+        }
+    }
+
+    pub fn command(&self, name: &'static str) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .commands
+            .entry(name)
+            .or_default() += 1;
+    }
+
+    pub fn timers_added(&self, count: usize) {
+        self.counters.lock().unwrap().timers_added += count as u64;
+    }
+
+    pub fn timers_removed(&self, count: usize) {
+        self.counters.lock().unwrap().timers_removed += count as u64;
+    }
+
+    /// `latency` は設定した時刻から実際に通知した時刻までの遅れ
+    pub fn timer_fired(&self, latency: Duration) {
+        let mut counters = self.counters.lock().unwrap();
+        counters.timers_fired += 1;
+        let latency = latency.as_secs_f64();
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|le| latency <= *le) {
+            counters.latency_buckets[index] += 1;
+        }
+        counters.latency_count += 1;
+        counters.latency_sum += latency;
+    }
+
+    pub fn api_error(&self, endpoint: &'static str) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .api_errors
+            .entry(endpoint)
+            .or_default() += 1;
+    }
+
+    /// Prometheus の text format で書き出す
+    pub fn render(&self, timer_states: &HashMap<String, TimerState>) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut out = String::new();
+
+        writeln!(
+            out,
+            "# HELP stimer_commands_total Commands executed by type."
+        )
+        .ok();
+        writeln!(out, "# TYPE stimer_commands_total counter").ok();
+        for (command, count) in counters.commands.iter() {
+            writeln!(
+                out,
+                "stimer_commands_total{{command=\"{}\"}} {}",
+                command, count
+            )
+            .ok();
+        }
+
+        for (name, help, value) in [
+            ("added", "Timers added.", counters.timers_added),
+            ("removed", "Timers removed.", counters.timers_removed),
+            ("fired", "Timers fired.", counters.timers_fired),
+        ] {
+            writeln!(out, "# HELP stimer_timers_{}_total {}", name, help).ok();
+            writeln!(out, "# TYPE stimer_timers_{}_total counter", name).ok();
+            writeln!(out, "stimer_timers_{}_total {}", name, value).ok();
+        }
+
+        writeln!(
+            out,
+            "# HELP stimer_notification_latency_seconds Delay between the scheduled time and the notification."
+        )
+        .ok();
+        writeln!(out, "# TYPE stimer_notification_latency_seconds histogram").ok();
+        let mut cumulative = 0;
+        for (le, count) in LATENCY_BUCKETS.iter().zip(counters.latency_buckets.iter()) {
+            cumulative += count;
+            writeln!(
+                out,
+                "stimer_notification_latency_seconds_bucket{{le=\"{}\"}} {}",
+                le, cumulative
+            )
+            .ok();
+        }
+        writeln!(
+            out,
+            "stimer_notification_latency_seconds_bucket{{le=\"+Inf\"}} {}",
+            counters.latency_count
+        )
+        .ok();
+        writeln!(
+            out,
+            "stimer_notification_latency_seconds_sum {}",
+            counters.latency_sum
+        )
+        .ok();
+        writeln!(
+            out,
+            "stimer_notification_latency_seconds_count {}",
+            counters.latency_count
+        )
+        .ok();
+
+        writeln!(
+            out,
+            "# HELP stimer_api_errors_total Failed traQ API calls by endpoint."
+        )
+        .ok();
+        writeln!(out, "# TYPE stimer_api_errors_total counter").ok();
+        for (endpoint, count) in counters.api_errors.iter() {
+            writeln!(
+                out,
+                "stimer_api_errors_total{{endpoint=\"{}\"}} {}",
+                endpoint, count
+            )
+            .ok();
+        }
+
+        let idle = timer_states
+            .values()
+            .filter(|state| matches!(state, TimerState::Idle(_)))
+            .count();
+        writeln!(out, "# HELP stimer_timers Timers by state.").ok();
+        writeln!(out, "# TYPE stimer_timers gauge").ok();
+        writeln!(out, "stimer_timers{{state=\"idle\"}} {}", idle).ok();
+        writeln!(
+            out,
+            "stimer_timers{{state=\"removed\"}} {}",
+            timer_states.len() - idle
+        )
+        .ok();
+
+        out
+    }
+}
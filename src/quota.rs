@@ -0,0 +1,73 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// ユーザーごとに `interval` の間の実行回数を `max_count` 回までに制限する
+#[derive(Debug, Default)]
+pub struct UserRateLimiter {
+    /// user_name を key, interval 内に実行した時刻を value に持つ
+    history: HashMap<String, VecDeque<Instant>>,
+}
+impl UserRateLimiter {
+    /// 実行できる場合は実行したことを記録して true を返す
+    pub fn try_acquire(&mut self, user_name: &str, max_count: usize, interval: Duration) -> bool {
+        self.try_acquire_at(user_name, max_count, interval, Instant::now())
+    }
+
+    fn try_acquire_at(
+        &mut self,
+        user_name: &str,
+        max_count: usize,
+        interval: Duration,
+        now: Instant,
+    ) -> bool {
+        // interval より前の記録を捨て、記録が残っていないユーザーは map から取り除く
+        self.history.retain(|_, history| {
+            while let Some(&time) = history.front() {
+                if now.duration_since(time) < interval {
+                    break;
+                }
+                history.pop_front();
+            }
+            !history.is_empty()
+        });
+
+        let history = self.history.entry(user_name.to_string()).or_default();
+        if history.len() >= max_count {
+            return false;
+        }
+        history.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit() {
+        let mut limiter = UserRateLimiter::default();
+        let interval = Duration::from_secs(60);
+        let start = Instant::now();
+        assert!(limiter.try_acquire_at("a", 2, interval, start));
+        assert!(limiter.try_acquire_at("a", 2, interval, start));
+        assert!(!limiter.try_acquire_at("a", 2, interval, start));
+        assert!(limiter.try_acquire_at("b", 2, interval, start));
+        assert!(limiter.try_acquire_at("a", 2, interval, start + interval));
+    }
+
+    #[test]
+    fn prune_expired_users() {
+        let mut limiter = UserRateLimiter::default();
+        let interval = Duration::from_secs(60);
+        let start = Instant::now();
+        limiter.try_acquire_at("a", 2, interval, start);
+        limiter.try_acquire_at("b", 2, interval, start + interval / 2);
+        limiter.try_acquire_at("c", 2, interval, start + interval);
+        let mut users = limiter.history.keys().cloned().collect::<Vec<_>>();
+        users.sort();
+        assert_eq!(users, vec!["b", "c"]);
+    }
+}
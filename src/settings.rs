@@ -0,0 +1,325 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono_tz::Tz;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use crate::{
+    channel_settings::Language,
+    date::TimeWindow,
+    duration::{format_duration, format_duration_short, parse_duration},
+    store,
+};
+
+pub const DEFAULT_MESSAGE: &str = "時間になりました :blob_bongo:";
+
+/// [`SettingsStore`] に保存できる設定
+pub trait Settings: Clone + Default + Serialize + DeserializeOwned {
+    type Update;
+
+    fn apply(&mut self, update: Self::Update);
+}
+
+/// id を key に持つ設定
+///
+/// 変更のたびに `path` の JSON ファイルに保存する
+#[derive(Debug)]
+pub struct SettingsStore<T> {
+    path: PathBuf,
+    settings: Mutex<HashMap<String, T>>,
+}
+impl<T: Settings> SettingsStore<T> {
+    pub fn load(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            settings: Mutex::new(store::load(path)),
+        }
+    }
+
+    /// 設定していない場合はデフォルトの設定を返す
+    pub async fn get(&self, id: &str) -> T {
+        self.settings
+            .lock()
+            .await
+            .get(id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// 設定を変更して保存し、変更後の設定を返す
+    pub async fn update(&self, id: &str, update: T::Update) -> T {
+        let mut settings = self.settings.lock().await;
+        let entry = settings.entry(id.to_string()).or_default();
+        entry.apply(update);
+        let entry = entry.clone();
+        if let Err(e) = store::save(&self.path, &*settings).await {
+            log::error!("{}", e);
+        }
+        entry
+    }
+}
+
+/// traQ の user id を key に持つユーザーごとの設定
+pub type UserSettingsStore = SettingsStore<UserSettings>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Delivery {
+    /// タイマーを設定したチャンネルでメンションする
+    #[default]
+    Channel,
+    /// DM で通知する
+    Dm,
+}
+
+/// タイマーを設定したときの返信の詳しさ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confirm {
+    /// スタンプのみ (日時で指定した場合は解釈した日時も返信する)
+    #[default]
+    Stamp,
+    /// 終了予定時刻を返信する
+    Time,
+    /// 終了予定時刻・残り時間・取り消し方法を返信する
+    Full,
+}
+
+/// DND (通知を控えてほしい時間帯) に通知するタイマーの扱い
+/// `--urgent` をつけたタイマーは DND を無視して通知する
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DndAction {
+    /// DND が終わるまで通知を保留する
+    #[default]
+    Hold,
+    /// メンションせずに通知する
+    Silent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dnd {
+    pub window: TimeWindow,
+    pub action: DndAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserSettings {
+    /// メッセージを省略したときの通知メッセージ (None の場合は [`DEFAULT_MESSAGE`])
+    pub message: Option<String>,
+    pub delivery: Delivery,
+    pub confirm: Confirm,
+    /// 日時の解釈と表示に使うタイムゾーン
+    pub timezone: Tz,
+    /// snooze コマンドで時間を省略したときに延長する時間
+    pub snooze: Duration,
+    /// ユーザーのタイムゾーンで解釈する
+    pub dnd: Option<Dnd>,
+}
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            message: None,
+            delivery: Delivery::default(),
+            confirm: Confirm::default(),
+            timezone: chrono_tz::Asia::Tokyo,
+            snooze: Duration::from_secs(5 * 60),
+            dnd: None,
+        }
+    }
+}
+impl Settings for UserSettings {
+    type Update = UserSettingUpdate;
+
+    fn apply(&mut self, update: UserSettingUpdate) {
+        let default = Self::default();
+        match update {
+            UserSettingUpdate::Message(message) => self.message = message,
+            UserSettingUpdate::Delivery(delivery) => {
+                self.delivery = delivery.unwrap_or(default.delivery)
+            }
+            UserSettingUpdate::Confirm(confirm) => {
+                self.confirm = confirm.unwrap_or(default.confirm)
+            }
+            UserSettingUpdate::Timezone(timezone) => {
+                self.timezone = timezone.unwrap_or(default.timezone)
+            }
+            UserSettingUpdate::Snooze(snooze) => self.snooze = snooze.unwrap_or(default.snooze),
+            UserSettingUpdate::Dnd(dnd) => self.dnd = dnd,
+        }
+    }
+}
+impl UserSettings {
+    pub fn default_message(&self) -> &str {
+        self.message.as_deref().unwrap_or(DEFAULT_MESSAGE)
+    }
+
+    /// `config` コマンドで表示する設定の一覧
+    pub fn describe(&self, language: Language) -> String {
+        format!(
+            "|{}|\n|---|---|\n|message|{}|\n|delivery|{}|\n|confirm|{}|\n|tz|{}|\n|snooze|{}|\n|dnd|{}|",
+            language.pick("項目|値", "Key|Value"),
+            self.default_message(),
+            match self.delivery {
+                Delivery::Channel => "channel",
+                Delivery::Dm => "dm",
+            },
+            match self.confirm {
+                Confirm::Stamp => "stamp",
+                Confirm::Time => "time",
+                Confirm::Full => "full",
+            },
+            self.timezone.name(),
+            match language {
+                Language::Ja => format_duration(self.snooze),
+                Language::En => format_duration_short(self.snooze),
+            },
+            match self.dnd {
+                Some(Dnd { window, action }) => format!(
+                    "{} ({})",
+                    window,
+                    match action {
+                        DndAction::Hold => "hold",
+                        DndAction::Silent => "silent",
+                    }
+                ),
+                None => "-".to_string(),
+            }
+        )
+    }
+}
+
+/// 設定の変更
+/// None の場合はデフォルトに戻す
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserSettingUpdate {
+    Message(Option<String>),
+    Delivery(Option<Delivery>),
+    Confirm(Option<Confirm>),
+    Timezone(Option<Tz>),
+    Snooze(Option<Duration>),
+    Dnd(Option<Dnd>),
+}
+impl UserSettingUpdate {
+    /// like `confirm full`, `tz America/Los_Angeles`, `message reset`
+    pub fn parse(
+        key: &str,
+        value: &str,
+        max_duration: Duration,
+        language: Language,
+    ) -> Result<Self, String> {
+        let value = if value == "reset" { None } else { Some(value) };
+        match key {
+            "message" | "msg" => Ok(Self::Message(value.map(|value| value.to_string()))),
+            "delivery" => Ok(Self::Delivery(
+                value
+                    .map(|value| match value {
+                        "channel" => Ok(Delivery::Channel),
+                        "dm" => Ok(Delivery::Dm),
+                        _ => Err(match language {
+                            Language::Ja => {
+                                format!("`{}` は channel か dm で指定してください", value)
+                            }
+                            Language::En => format!("`{}` must be channel or dm", value),
+                        }),
+                    })
+                    .transpose()?,
+            )),
+            "confirm" => Ok(Self::Confirm(
+                value
+                    .map(|value| match value {
+                        "stamp" | "off" => Ok(Confirm::Stamp),
+                        "time" => Ok(Confirm::Time),
+                        "full" | "on" => Ok(Confirm::Full),
+                        _ => Err(match language {
+                            Language::Ja => format!(
+                                "`{}` は stamp, time, full のいずれかで指定してください",
+                                value
+                            ),
+                            Language::En => {
+                                format!("`{}` must be one of stamp, time or full", value)
+                            }
+                        }),
+                    })
+                    .transpose()?,
+            )),
+            "tz" | "timezone" => Ok(Self::Timezone(
+                value
+                    .map(|value| {
+                        value
+                            .parse()
+                            .map_err(|_| invalid_timezone_message(value, language))
+                    })
+                    .transpose()?,
+            )),
+            "snooze" => Ok(Self::Snooze(
+                value
+                    .map(|value| match parse_duration(value, max_duration) {
+                        Ok((duration, rest)) if rest.trim().is_empty() => Ok(duration),
+                        Ok(_) => Err(trailing_characters_message(language)),
+                        Err(e) => Err(e.describe(language)),
+                    })
+                    .transpose()?,
+            )),
+            "dnd" => Ok(Self::Dnd(
+                value
+                    .map(|value| {
+                        let mut splitted = value.split_whitespace();
+                        let window =
+                            TimeWindow::parse(splitted.next().unwrap_or_default(), language)?;
+                        let action = match splitted.next() {
+                            None | Some("hold") => DndAction::Hold,
+                            Some("silent") => DndAction::Silent,
+                            Some(action) => {
+                                return Err(match language {
+                                    Language::Ja => {
+                                        format!("`{}` は hold か silent で指定してください", action)
+                                    }
+                                    Language::En => {
+                                        format!("`{}` must be hold or silent", action)
+                                    }
+                                })
+                            }
+                        };
+                        Ok(Dnd { window, action })
+                    })
+                    .transpose()?,
+            )),
+            _ => Err(unknown_key_message(key, language)),
+        }
+    }
+}
+
+pub fn invalid_timezone_message(value: &str, language: Language) -> String {
+    match language {
+        Language::Ja => format!(
+            "`{}` は IANA タイムゾーン名 (Asia/Tokyo など) ではありません",
+            value
+        ),
+        Language::En => format!(
+            "`{}` is not an IANA time zone name (like Asia/Tokyo)",
+            value
+        ),
+    }
+}
+
+pub fn unknown_key_message(key: &str, language: Language) -> String {
+    match language {
+        Language::Ja => format!("設定項目 `{}` はありません", key),
+        Language::En => format!("Unknown setting `{}`", key),
+    }
+}
+
+pub fn trailing_characters_message(language: Language) -> String {
+    language
+        .pick(
+            "時間の後に余計な文字があります",
+            "Unexpected characters after the duration",
+        )
+        .to_string()
+}
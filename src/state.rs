@@ -0,0 +1,156 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    channel_settings::ChannelSettings,
+    events::{self, Event},
+    history::CompletedTimer,
+    settings::UserSettings,
+    store, Message, TimerState,
+};
+
+/// DATA_DIR に保存するファイル
+pub const TIMERS_FILE: &str = "timers.json";
+pub const USER_SETTINGS_FILE: &str = "user_settings.json";
+pub const CHANNEL_SETTINGS_FILE: &str = "channel_settings.json";
+pub const HISTORY_FILE: &str = "history.json";
+/// `stats` コマンド用のタイマーの記録 (JSON Lines)
+pub const EVENTS_FILE: &str = "events.jsonl";
+
+/// `export-state` で書き出す形式のバージョン
+/// 形式を変えたときは 1 増やし、[`State::parse`] で古い形式を変換する
+pub const STATE_VERSION: u32 = 1;
+
+/// BOT の状態 (`export-state` / `import-state` で使う)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub version: u32,
+    /// 通知前のタイマー (削除済みのタイマーは含まない)
+    pub timers: Vec<Message>,
+    pub user_settings: HashMap<String, UserSettings>,
+    pub channel_settings: HashMap<String, ChannelSettings>,
+    /// traQ の user id を key に持つ終了したタイマー
+    pub history: HashMap<String, VecDeque<CompletedTimer>>,
+    /// `stats` コマンド用のタイマーの記録 (古い順)
+    pub events: Vec<Event>,
+}
+impl State {
+    /// `data_dir` に保存されている状態を、ファイルを変更せずに読み込む
+    pub fn read(data_dir: &Path) -> Result<Self, String> {
+        Ok(Self {
+            version: STATE_VERSION,
+            timers: store::read(&data_dir.join(TIMERS_FILE))?,
+            user_settings: store::read(&data_dir.join(USER_SETTINGS_FILE))?,
+            channel_settings: store::read(&data_dir.join(CHANNEL_SETTINGS_FILE))?,
+            history: store::read(&data_dir.join(HISTORY_FILE))?,
+            events: events::load_events(&data_dir.join(EVENTS_FILE))?,
+        })
+    }
+
+    /// `data_dir` の状態を置き換える
+    /// BOT の実行中に置き換えると BOT に上書きされるので、BOT を止めてから実行する
+    pub async fn save(&self, data_dir: &Path) -> Result<(), String> {
+        store::save(&data_dir.join(TIMERS_FILE), &self.timers).await?;
+        store::save(&data_dir.join(USER_SETTINGS_FILE), &self.user_settings).await?;
+        store::save(
+            &data_dir.join(CHANNEL_SETTINGS_FILE),
+            &self.channel_settings,
+        )
+        .await?;
+        store::save(&data_dir.join(HISTORY_FILE), &self.history).await?;
+        events::save_events(&data_dir.join(EVENTS_FILE), &self.events).await
+    }
+
+    /// `export-state` で書き出した JSON を読み込む
+    /// `version` がない場合は 1 とみなす
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(content).map_err(|e| format!("Failed to parse state: {:?}", e))?;
+        let version = match value.get("version") {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .ok_or_else(|| format!("Invalid version: {}", version))?,
+        };
+        if version > STATE_VERSION as u64 {
+            return Err(format!(
+                "State version {} is newer than supported version {}",
+                version, STATE_VERSION
+            ));
+        }
+        let mut state: Self =
+            serde_json::from_value(value).map_err(|e| format!("Failed to parse state: {:?}", e))?;
+        state.version = STATE_VERSION;
+        Ok(state)
+    }
+}
+
+/// 通知前のタイマーを通知する順に並べる
+pub fn pending_messages(timer_states: &HashMap<String, TimerState>) -> Vec<Message> {
+    let mut messages = timer_states
+        .values()
+        .filter_map(|state| match state {
+            TimerState::Idle(message) => Some(message.clone()),
+            TimerState::Removed => None,
+        })
+        .collect::<Vec<_>>();
+    messages.sort();
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_without_version() {
+        // version がなく、後から追加した項目 (short_id など) もない古い形式
+        let state = State::parse(
+            r#"{
+                "timers": [
+                    {
+                        "id": "6bb86c45-65d5-458f-83c0-57116d81eca1",
+                        "message": "時間になりました",
+                        "time": { "secs_since_epoch": 1717200000, "nanos_since_epoch": 0 },
+                        "message_uuid": "6bb86c45-65d5-458f-83c0-57116d81eca1",
+                        "channel_id": "channel",
+                        "user_id": "SSlime",
+                        "user_uuid": "user"
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(state.version, 1);
+        assert_eq!(state.timers.len(), 1);
+        assert_eq!(state.timers[0].short_id, None);
+        assert!(state.user_settings.is_empty());
+        assert!(state.events.is_empty());
+    }
+
+    #[test]
+    fn parse_newer_version() {
+        assert!(State::parse(&format!(r#"{{"version": {}}}"#, STATE_VERSION + 1)).is_err());
+    }
+
+    #[test]
+    fn read_does_not_move_files() {
+        let data_dir = std::env::temp_dir().join(format!(
+            "bot-simple-timer-state-test-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let timers_path = data_dir.join(TIMERS_FILE);
+        std::fs::write(&timers_path, "not json").unwrap();
+
+        assert!(State::read(&data_dir).is_err());
+        assert_eq!(std::fs::read_to_string(&timers_path).unwrap(), "not json");
+
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// JSON ファイルから読み込む
+/// ファイルが存在しない場合は `T::default()` を返す
+///
+/// 読み込めないファイルをそのままにすると次の保存で空の内容に上書きされてしまうので、
+/// 解釈できない場合は `{ファイル名}.corrupt-{UNIX 時間}` に移してから `T::default()` を返し、
+/// 読み込み自体に失敗した場合や移せなかった場合は起動をやめる
+pub fn load<T: DeserializeOwned + Default>(path: &Path) -> T {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return T::default(),
+        Err(e) => panic!("Failed to read {}: {:?}", path.display(), e),
+    };
+    match serde_json::from_str(&content) {
+        Ok(value) => value,
+        Err(e) => {
+            log::error!("Failed to parse {}: {:?}", path.display(), e);
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let mut corrupt_path = path.as_os_str().to_owned();
+            corrupt_path.push(format!(".corrupt-{}", timestamp));
+            let corrupt_path = PathBuf::from(corrupt_path);
+            if let Err(e) = std::fs::rename(path, &corrupt_path) {
+                panic!(
+                    "Failed to move unparsable {} to {}: {:?}",
+                    path.display(),
+                    corrupt_path.display(),
+                    e
+                );
+            }
+            log::warn!(
+                "Moved unparsable {} to {}",
+                path.display(),
+                corrupt_path.display()
+            );
+            T::default()
+        }
+    }
+}
+
+/// JSON ファイルをそのまま読み込む (`export-state` 用)
+/// ファイルが存在しない場合は `T::default()` を返し、[`load`] と違って解釈できないファイルも移さない
+pub fn read<T: DeserializeOwned + Default>(path: &Path) -> Result<T, String> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(e) => return Err(format!("Failed to read {}: {:?}", path.display(), e)),
+    };
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse {}: {:?}", path.display(), e))
+}
+
+/// JSON ファイルに書き込む
+/// 書き込み途中で落ちても壊れないように、一時ファイルに書き込んでから置き換える
+pub async fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(value)
+        .map_err(|e| format!("Failed to serialize {}: {:?}", path.display(), e))?;
+
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {:?}", dir.display(), e))?;
+    }
+    let tmp_path = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp_path, content)
+        .await
+        .map_err(|e| format!("Failed to write {}: {:?}", tmp_path.display(), e))?;
+    tokio::fs::rename(&tmp_path, path)
+        .await
+        .map_err(|e| format!("Failed to rename {}: {:?}", tmp_path.display(), e))
+}
@@ -0,0 +1,61 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::watch;
+use traq_ws_bot::builder;
+
+use crate::{handler, timer::Timer, Resource};
+
+/// BOT を再起動するまでの最初の待ち時間 (再起動するたびに 2倍にする)
+const INITIAL_RESTART_WAIT: Duration = Duration::from_secs(1);
+const MAX_RESTART_WAIT: Duration = Duration::from_secs(5 * 60);
+/// これより長く動いていた場合は、待ち時間を最初の値に戻す
+const STABLE_RUNNING_TIME: Duration = Duration::from_secs(10 * 60);
+/// Timer が panic したときに再起動するまでの待ち時間
+const TIMER_RESTART_WAIT: Duration = Duration::from_secs(1);
+
+/// WebSocket の BOT を動かし続ける
+/// 終了したり panic したりした場合は、待ち時間を伸ばしながら作り直して再起動する
+pub async fn supervise_bot(token: String, resource: Arc<Resource>) {
+    let mut restart_wait = INITIAL_RESTART_WAIT;
+    loop {
+        let bot = builder(&token)
+            .insert_resource(resource.clone())
+            .on_message_created_with_resource(handler::on_message)
+            .on_direct_message_created_with_resource(handler::on_direct_message)
+            .build();
+        let started_at = Instant::now();
+        resource.health.set_bot_task_running(true);
+        match tokio::spawn(async move { bot.start().await }).await {
+            Ok(Ok(())) => log::error!("Bot stopped"),
+            Ok(Err(e)) => log::error!("Bot stopped with error: {:?}", e),
+            Err(e) => log::error!("Bot panicked: {:?}", e),
+        }
+        resource.health.set_bot_task_running(false);
+
+        if started_at.elapsed() >= STABLE_RUNNING_TIME {
+            restart_wait = INITIAL_RESTART_WAIT;
+        }
+        log::info!("Restarting bot after {:?}", restart_wait);
+        tokio::time::sleep(restart_wait).await;
+        restart_wait = (restart_wait * 2).min(MAX_RESTART_WAIT);
+    }
+}
+
+/// Timer を `shutdown` に true が送られるまで動かし続ける
+/// panic した場合は `new_timer` で作り直して再起動する (タイマーの状態は共有しているので引き継がれる)
+pub async fn supervise_timer(new_timer: impl Fn() -> Timer, shutdown: watch::Receiver<bool>) {
+    loop {
+        let mut timer = new_timer();
+        let shutdown = shutdown.clone();
+        match tokio::spawn(async move { timer.run(shutdown).await }).await {
+            Ok(()) => return,
+            Err(e) => log::error!("Timer panicked: {:?}", e),
+        }
+
+        log::info!("Restarting timer after {:?}", TIMER_RESTART_WAIT);
+        tokio::time::sleep(TIMER_RESTART_WAIT).await;
+    }
+}
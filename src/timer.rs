@@ -1,6 +1,14 @@
-use std::{cmp::Reverse, collections::BinaryHeap};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, BinaryHeap},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use chrono::Utc;
 
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch, Mutex};
 use traq_ws_bot::{
     openapi::{
         self,
@@ -9,7 +17,18 @@ use traq_ws_bot::{
     utils::create_configuration,
 };
 
-use crate::{Message, Operation, TimerState, Timers};
+use crate::{
+    channel_settings::{ChannelSettingsStore, Language, QuietAction},
+    events::{Event, EventKind, EventLog},
+    handler::{message_url, removed_summary},
+    health::Health,
+    history::{History, Outcome},
+    logging,
+    metrics::METRICS,
+    settings::{Delivery, DndAction, UserSettingsStore},
+    state::pending_messages,
+    store, LastFired, Message, Operation, Resource, TimerRef, TimerState, Timers,
+};
 
 const THUMBS_UP_ID: &str = "269095e6-c71c-4887-afb0-e42b5e2ac73b";
 const KAN_ID: &str = "68c4cc50-487d-44a1-ade3-0808023037b8";
@@ -18,60 +37,133 @@ const GIT_WORKFLOW_CANCEL_ID: &str = "13248e15-240f-4d8c-8c7a-47e84e773702";
 const GIT_WORKFLOW_FAIL_ID: &str = "b3c6a7c7-aeb8-4f45-aee8-380c245089db";
 const PERSON_GESTURING_NO_ID: &str = "35022768-bddb-458c-945f-8fd3da28be3a";
 
+/// タイマーがなくても Timer のループを回す間隔 (health check 用)
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(Debug)]
 pub struct Timer {
     token: String,
-    rx: mpsc::Receiver<Operation>,
+    health: Arc<Health>,
+    events: Arc<EventLog>,
+    history: Arc<History>,
+    rx: Arc<Mutex<mpsc::Receiver<Operation>>>,
     messages: BinaryHeap<Reverse<Message>>,
     /// message_id を key, state を value に持つ
     timer_states: Timers,
+    user_settings: Arc<UserSettingsStore>,
+    channel_settings: Arc<ChannelSettingsStore>,
+    last_fired: LastFired,
+    /// 通知前のタイマーを保存するファイル
+    timers_path: PathBuf,
 }
 impl Timer {
-    pub fn new(token: String, rx: mpsc::Receiver<Operation>, timers: Timers) -> Self {
+    pub fn new(
+        resource: &Resource,
+        rx: Arc<Mutex<mpsc::Receiver<Operation>>>,
+        timers_path: PathBuf,
+    ) -> Self {
         Self {
-            token,
+            token: resource.token.clone(),
+            health: resource.health.clone(),
+            events: resource.events.clone(),
+            history: resource.history.clone(),
             rx,
             messages: BinaryHeap::new(),
-            timer_states: timers,
+            timer_states: resource.timers.clone(),
+            user_settings: resource.user_settings.clone(),
+            channel_settings: resource.channel_settings.clone(),
+            last_fired: resource.last_fired.clone(),
+            timers_path,
         }
     }
 
-    pub async fn run(&mut self) {
+    /// `shutdown` に true が送られるまでタイマーを処理する
+    /// 終了するときは、受け取り済みの操作をすべて処理してからタイマーを保存する
+    pub async fn run(&mut self, mut shutdown: watch::Receiver<bool>) {
+        self.restore().await;
+        let rx = self.rx.clone();
         loop {
+            if *shutdown.borrow() {
+                break;
+            }
+            self.health.scheduler_heartbeat();
             let now = std::time::SystemTime::now();
             let next_time = self.messages.peek().map(|m| m.0.time);
             if let Some(next_time) = next_time {
                 // 指定時間が来ている場合は即座に通知する
                 if next_time <= now {
                     if let Some(message) = self.consume_top_message().await {
-                        self.notify(message).await
+                        self.notify(message).await;
+                        self.save().await;
                     }
                     continue;
                 }
 
                 // そうでない場合は、指定時間まで待機 OR 新たなタイマーが追加されるまで待機
+                // 生きていることを示すために、長くても HEARTBEAT_INTERVAL ごとにループを回す
                 let duration = next_time.duration_since(now).unwrap();
                 tokio::select! {
-                    _ = tokio::time::sleep(duration) => {
-                        log::debug!("Timer expired: {:?}", next_time);
-                        if let Some(message) = self.consume_top_message().await {
-                            self.notify(message).await
-                        }
-                    }
-                    operation = self.rx.recv() => {
+                    _ = tokio::time::sleep(duration.min(HEARTBEAT_INTERVAL)) => {}
+                    operation = async { rx.lock().await.recv().await } => {
                         log::debug!("Received operation: {:?}", operation);
                         if let Some(operation) = operation {
                             self.operation(operation).await;
+                            self.save().await;
                         }
                     }
+                    _ = shutdown.changed() => {}
                 }
             } else {
                 // タイマーがない場合は、新たなタイマーが追加されるまで待機
-                if let Some(operation) = self.rx.recv().await {
-                    self.operation(operation).await;
+                tokio::select! {
+                    operation = async { rx.lock().await.recv().await } => {
+                        if let Some(operation) = operation {
+                            self.operation(operation).await;
+                            self.save().await;
+                        }
+                    }
+                    _ = tokio::time::sleep(HEARTBEAT_INTERVAL) => {}
+                    _ = shutdown.changed() => {}
                 }
             }
         }
+
+        // 以降の送信は失敗させて、受け取り済みの操作だけを処理する
+        // (終了処理の開始前に確認を通ったハンドラーの操作が取り残されないようにする)
+        let mut drained = 0;
+        let mut rx = rx.lock().await;
+        rx.close();
+        while let Ok(operation) = rx.try_recv() {
+            log::debug!("Received operation during shutdown: {:?}", operation);
+            self.operation(operation).await;
+            drained += 1;
+        }
+        self.save().await;
+        log::info!(
+            "Timer stopped: processed {} queued operations, saved {} pending timers",
+            drained,
+            pending_messages(&*self.timer_states.lock().await).len()
+        );
+    }
+
+    /// timer_states から通知前のタイマーを読み込む (起動時と再起動時)
+    /// 削除済みのタイマーは通知されないので timer_states からも取り除く
+    async fn restore(&mut self) {
+        let mut timer_states = self.timer_states.lock().await;
+        timer_states.retain(|_, state| matches!(state, TimerState::Idle(_)));
+        self.messages = pending_messages(&timer_states)
+            .into_iter()
+            .map(Reverse)
+            .collect();
+        log::info!("Restored {} timers", self.messages.len());
+    }
+
+    /// 通知前のタイマーを保存する
+    async fn save(&self) {
+        let messages = pending_messages(&*self.timer_states.lock().await);
+        if let Err(e) = store::save(&self.timers_path, &messages).await {
+            log::error!("{}", e);
+        }
     }
 
     /// messages から 1つ取り出し、timer_states と比較して、有効な場合は Message を返す
@@ -79,23 +171,17 @@ impl Timer {
     async fn consume_top_message(&mut self) -> Option<Message> {
         if let Some(message) = self.messages.pop() {
             let message = message.0;
-            let timer_state = {
-                self.timer_states
-                    .lock()
-                    .await
-                    .get(&message.message_uuid)
-                    .cloned()
-            };
+            let timer_state = { self.timer_states.lock().await.get(&message.id).cloned() };
             if let Some(state) = timer_state {
                 match state {
-                    TimerState::Idle(_, _) => {
+                    TimerState::Idle(_) => {
                         log::debug!("Timer is idle: {:?}", message);
-                        self.timer_states.lock().await.remove(&message.message_uuid);
+                        self.timer_states.lock().await.remove(&message.id);
                         Some(message)
                     }
                     TimerState::Removed => {
                         log::debug!("Timer already removed: {:?}", message);
-                        self.timer_states.lock().await.remove(&message.message_uuid);
+                        self.timer_states.lock().await.remove(&message.id);
                         None
                     }
                 }
@@ -108,22 +194,109 @@ impl Timer {
         }
     }
 
-    async fn notify(&self, message: Message) {
+    /// タイマーを設定したコマンドの span でログを出しながら通知する
+    async fn notify(&mut self, message: Message) {
+        logging::scope(message.span(), self.deliver(message)).await
+    }
+
+    async fn deliver(&mut self, message: Message) {
+        log::info!("Notify timer {}", message.id);
         log::debug!("Notify: {:?}", message);
-        let configuration = create_configuration(&self.token);
-        let res = openapi::apis::message_api::post_message(
-            &configuration,
-            &message.channel_id,
-            Some(models::PostMessageRequest {
-                content: format!("@{} {}", message.user_id, message.message),
-                embed: Some(true),
-            }),
-        )
-        .await;
-        if let Err(e) = res {
-            log::error!("Failed to post message: {:?}", e);
+        let user_settings = self.user_settings.get(&message.user_uuid).await;
+        let mut mention = true;
+        if let Some(dnd) = user_settings.dnd.filter(|_| !message.urgent) {
+            let now = Utc::now().with_timezone(&user_settings.timezone);
+            if let Some(end) = dnd.window.end_after(now) {
+                match dnd.action {
+                    DndAction::Hold => {
+                        self.reschedule(message, end.into()).await;
+                        return;
+                    }
+                    DndAction::Silent => mention = false,
+                }
+            }
+        }
+
+        let mut delivery = user_settings.delivery;
+        if delivery == Delivery::Channel {
+            let channel_settings = self.channel_settings.get(&message.channel_id).await;
+            if let Some(quiet_hours) = channel_settings.quiet_hours {
+                let now = Utc::now().with_timezone(&channel_settings.timezone);
+                if let Some(end) = quiet_hours.window.end_after(now) {
+                    match quiet_hours.action {
+                        QuietAction::Defer => {
+                            self.reschedule(message, end.into()).await;
+                            return;
+                        }
+                        QuietAction::Dm => delivery = Delivery::Dm,
+                    }
+                }
+            }
         }
 
+        // DND などで遅らせた場合は、遅らせた後の時刻からの遅れになる
+        METRICS.timer_fired(message.time.elapsed().unwrap_or_default());
+        self.events
+            .record(Event::new(EventKind::Fired, &message))
+            .await;
+        let configuration = create_configuration(&self.token);
+        let is_delivered = match delivery {
+            Delivery::Channel => {
+                let res = openapi::apis::message_api::post_message(
+                    &configuration,
+                    &message.channel_id,
+                    Some(models::PostMessageRequest {
+                        // メンションしない場合も誰のタイマーかわかるようにアイコンをつける
+                        content: if mention {
+                            format!("@{} {}", message.user_id, message.message)
+                        } else {
+                            format!(":@{}: {}", message.user_id, message.message)
+                        },
+                        embed: Some(true),
+                    }),
+                )
+                .await;
+                match res {
+                    Ok(_) => {
+                        self.health.notified();
+                        true
+                    }
+                    Err(e) => {
+                        log::error!("Failed to post message: {:?}", e);
+                        METRICS.api_error("post_message");
+                        false
+                    }
+                }
+            }
+            // DM ではどのタイマーの通知かわかるように、設定したメッセージへのリンクをつける
+            Delivery::Dm => {
+                let res = openapi::apis::message_api::post_direct_message(
+                    &configuration,
+                    &message.user_uuid,
+                    Some(models::PostMessageRequest {
+                        content: format!(
+                            "{}\n{}",
+                            message.message,
+                            message_url(&message.message_uuid, false)
+                        ),
+                        embed: Some(true),
+                    }),
+                )
+                .await;
+                match res {
+                    Ok(_) => {
+                        self.health.notified();
+                        true
+                    }
+                    Err(e) => {
+                        log::error!("Failed to post direct message: {:?}", e);
+                        METRICS.api_error("post_direct_message");
+                        false
+                    }
+                }
+            }
+        };
+
         let res = openapi::apis::stamp_api::remove_message_stamp(
             &configuration,
             &message.message_uuid,
@@ -131,6 +304,7 @@ impl Timer {
         );
         if let Err(e) = res.await {
             log::error!("Failed to remove stamp: {:?}", e);
+            METRICS.api_error("remove_message_stamp");
         }
         let res = openapi::apis::stamp_api::add_message_stamp(
             &configuration,
@@ -140,16 +314,82 @@ impl Timer {
         );
         if let Err(e) = res.await {
             log::error!("Failed to add stamp: {:?}", e);
+            METRICS.api_error("add_message_stamp");
+        }
+
+        let outcome = if is_delivered {
+            Outcome::Fired
+        } else {
+            Outcome::Failed
+        };
+        self.history.record(message.clone(), outcome).await;
+
+        self.last_fired
+            .lock()
+            .await
+            .insert(message.user_uuid.clone(), message);
+    }
+
+    /// 通知せずに `time` に通知するタイマーとして設定し直す
+    async fn reschedule(&mut self, message: Message, time: SystemTime) {
+        log::debug!("Reschedule: {:?} to {:?}", message, time);
+        let message = Message { time, ..message };
+        self.timer_states
+            .lock()
+            .await
+            .insert(message.id.clone(), TimerState::Idle(message.clone()));
+        self.messages.push(Reverse(message));
+    }
+
+    /// 複数のタイマーを指定して削除したときの結果を返信する
+    async fn post_removed_summary(
+        &self,
+        channel_id: &str,
+        removed: &[Message],
+        refused: &[Message],
+        not_found: &[TimerRef],
+    ) {
+        let language = self.channel_settings.get(channel_id).await.language;
+        let configuration = create_configuration(&self.token);
+        let res = openapi::apis::message_api::post_message(
+            &configuration,
+            channel_id,
+            Some(models::PostMessageRequest {
+                content: removed_summary(removed, refused, not_found, language),
+                embed: None,
+            }),
+        )
+        .await;
+        if let Err(e) = res {
+            log::error!("Failed to post message: {:?}", e);
+            METRICS.api_error("post_message");
         }
     }
 
+    async fn record_cancelled(&self, messages: &[Message]) {
+        for message in messages {
+            self.events
+                .record(Event::new(EventKind::Cancelled, message))
+                .await;
+            self.history
+                .record(message.clone(), Outcome::Cancelled)
+                .await;
+        }
+    }
+
+    /// 操作を送ったコマンドの span でログを出しながら操作を処理する
     async fn operation(&mut self, operation: Operation) {
+        logging::scope(operation.span(), self.apply(operation)).await
+    }
+
+    async fn apply(&mut self, operation: Operation) {
         match operation {
             Operation::Add(message) => {
-                self.timer_states.lock().await.insert(
-                    message.message_uuid.clone(),
-                    TimerState::Idle(message.user_id.clone(), message.time),
-                );
+                METRICS.timers_added(1);
+                self.timer_states
+                    .lock()
+                    .await
+                    .insert(message.id.clone(), TimerState::Idle(message.clone()));
                 self.messages.push(Reverse(message.clone()));
                 let configuration = create_configuration(&self.token);
                 let res = openapi::apis::stamp_api::add_message_stamp(
@@ -160,71 +400,197 @@ impl Timer {
                 );
                 if let Err(e) = res.await {
                     log::error!("Failed to add stamp: {:?}", e);
+                    METRICS.api_error("add_message_stamp");
                 }
             }
             Operation::Remove {
-                remove_message_uuid: message_uuid,
+                targets,
                 trigger_message_uuid,
+                trigger_channel_id,
                 trigger_user_name: user_name,
+                trigger_is_admin,
+                ..
             } => {
-                let is_removed;
-                {
+                // import で設定したタイマーは、import を実行したメッセージの URL でまとめて削除できる
+                // 他人のタイマーが含まれていても、削除できるタイマーは削除する
+                let (removed_messages, refused_messages) = {
                     let mut timer_states = self.timer_states.lock().await;
-                    let state = timer_states.get(&message_uuid).cloned();
-                    if let Some(state) = state {
-                        is_removed = state == TimerState::Removed;
-                        if let TimerState::Idle(user_id, _) = state {
-                            if user_id != user_name {
-                                let configuration = create_configuration(&self.token);
-                                let res = openapi::apis::stamp_api::add_message_stamp(
-                                    &configuration,
-                                    &trigger_message_uuid,
-                                    PERSON_GESTURING_NO_ID,
-                                    Some(PostMessageStampRequest { count: 1 }),
-                                );
-                                if let Err(e) = res.await {
-                                    log::error!("Failed to add stamp: {:?}", e);
-                                }
-                                return;
+                    let (removed_messages, refused_messages): (Vec<_>, Vec<_>) = timer_states
+                        .values()
+                        .filter_map(|state| match state {
+                            TimerState::Idle(message)
+                                if targets.iter().any(|target| message.matches(target)) =>
+                            {
+                                Some(message.clone())
                             }
-                        }
-                        timer_states.insert(message_uuid.clone(), TimerState::Removed);
-                    } else {
-                        is_removed = true;
+                            _ => None,
+                        })
+                        .partition(|message| trigger_is_admin || message.user_id == user_name);
+                    for message in removed_messages.iter() {
+                        timer_states.insert(message.id.clone(), TimerState::Removed);
                     }
-                }
-                if is_removed {
-                    let configuration = create_configuration(&self.token);
+                    (removed_messages, refused_messages)
+                };
+                let not_found = targets
+                    .iter()
+                    .filter(|target| {
+                        !removed_messages
+                            .iter()
+                            .chain(refused_messages.iter())
+                            .any(|message| message.matches(target))
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let is_bulk = targets.len() > 1 || removed_messages.len() > 1;
+                let configuration = create_configuration(&self.token);
+                if removed_messages.is_empty() {
+                    if is_bulk {
+                        self.post_removed_summary(
+                            &trigger_channel_id,
+                            &removed_messages,
+                            &refused_messages,
+                            &not_found,
+                        )
+                        .await;
+                    }
+                    let stamp_id = if refused_messages.is_empty() {
+                        GIT_WORKFLOW_FAIL_ID
+                    } else {
+                        PERSON_GESTURING_NO_ID
+                    };
                     let res = openapi::apis::stamp_api::add_message_stamp(
                         &configuration,
                         &trigger_message_uuid,
-                        GIT_WORKFLOW_FAIL_ID,
+                        stamp_id,
                         Some(PostMessageStampRequest { count: 1 }),
                     );
                     if let Err(e) = res.await {
                         log::error!("Failed to add stamp: {:?}", e);
+                        METRICS.api_error("add_message_stamp");
                     }
                     return;
                 }
-                let configuration = create_configuration(&self.token);
-                let res = openapi::apis::stamp_api::remove_message_stamp(
-                    &configuration,
-                    &message_uuid,
-                    THUMBS_UP_ID,
-                );
-                if let Err(e) = res.await {
-                    log::error!("Failed to remove stamp: {:?}", e);
+                METRICS.timers_removed(removed_messages.len());
+                self.record_cancelled(&removed_messages).await;
+                for message_uuid in source_message_uuids(&removed_messages) {
+                    let res = openapi::apis::stamp_api::remove_message_stamp(
+                        &configuration,
+                        &message_uuid,
+                        THUMBS_UP_ID,
+                    );
+                    if let Err(e) = res.await {
+                        log::error!("Failed to remove stamp: {:?}", e);
+                        METRICS.api_error("remove_message_stamp");
+                    }
+                    let res = openapi::apis::stamp_api::add_message_stamp(
+                        &configuration,
+                        &message_uuid,
+                        GIT_WORKFLOW_CANCEL_ID,
+                        Some(PostMessageStampRequest { count: 1 }),
+                    );
+                    if let Err(e) = res.await {
+                        log::error!("Failed to add stamp: {:?}", e);
+                        METRICS.api_error("add_message_stamp");
+                    }
                 }
-                let res = openapi::apis::stamp_api::add_message_stamp(
+                if is_bulk || !refused_messages.is_empty() {
+                    self.post_removed_summary(
+                        &trigger_channel_id,
+                        &removed_messages,
+                        &refused_messages,
+                        &not_found,
+                    )
+                    .await;
+                }
+                let res = openapi::apis::message_api::add_message_stamp(
                     &configuration,
-                    &message_uuid,
-                    GIT_WORKFLOW_CANCEL_ID,
+                    &trigger_message_uuid,
+                    GIT_WORKFLOW_SUCCESS_ID,
                     Some(PostMessageStampRequest { count: 1 }),
                 );
                 if let Err(e) = res.await {
                     log::error!("Failed to add stamp: {:?}", e);
+                    METRICS.api_error("add_message_stamp");
                 }
-                let res = openapi::apis::message_api::add_message_stamp(
+            }
+            Operation::Purge {
+                user_name,
+                trigger_message_uuid,
+                trigger_channel_id,
+                ..
+            } => {
+                let removed_messages = {
+                    let mut timer_states = self.timer_states.lock().await;
+                    let removed_messages = timer_states
+                        .values()
+                        .filter_map(|state| match state {
+                            TimerState::Idle(message) if message.user_id == user_name => {
+                                Some(message.clone())
+                            }
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    for message in removed_messages.iter() {
+                        timer_states.insert(message.id.clone(), TimerState::Removed);
+                    }
+                    removed_messages
+                };
+                METRICS.timers_removed(removed_messages.len());
+                self.record_cancelled(&removed_messages).await;
+
+                let language = self
+                    .channel_settings
+                    .get(&trigger_channel_id)
+                    .await
+                    .language;
+                let configuration = create_configuration(&self.token);
+                for message_uuid in source_message_uuids(&removed_messages) {
+                    let res = openapi::apis::stamp_api::remove_message_stamp(
+                        &configuration,
+                        &message_uuid,
+                        THUMBS_UP_ID,
+                    );
+                    if let Err(e) = res.await {
+                        log::error!("Failed to remove stamp: {:?}", e);
+                        METRICS.api_error("remove_message_stamp");
+                    }
+                    let res = openapi::apis::stamp_api::add_message_stamp(
+                        &configuration,
+                        &message_uuid,
+                        GIT_WORKFLOW_CANCEL_ID,
+                        Some(PostMessageStampRequest { count: 1 }),
+                    );
+                    if let Err(e) = res.await {
+                        log::error!("Failed to add stamp: {:?}", e);
+                        METRICS.api_error("add_message_stamp");
+                    }
+                }
+
+                let res = openapi::apis::message_api::post_message(
+                    &configuration,
+                    &trigger_channel_id,
+                    Some(models::PostMessageRequest {
+                        content: match language {
+                            Language::Ja => format!(
+                                "@{} のタイマーを {} 件削除しました",
+                                user_name,
+                                removed_messages.len()
+                            ),
+                            Language::En => format!(
+                                "Removed {} timers of @{}",
+                                removed_messages.len(),
+                                user_name
+                            ),
+                        },
+                        embed: None,
+                    }),
+                )
+                .await;
+                if let Err(e) = res {
+                    log::error!("Failed to post message: {:?}", e);
+                    METRICS.api_error("post_message");
+                }
+                let res = openapi::apis::stamp_api::add_message_stamp(
                     &configuration,
                     &trigger_message_uuid,
                     GIT_WORKFLOW_SUCCESS_ID,
@@ -232,8 +598,18 @@ impl Timer {
                 );
                 if let Err(e) = res.await {
                     log::error!("Failed to add stamp: {:?}", e);
+                    METRICS.api_error("add_message_stamp");
                 }
             }
         }
     }
 }
+
+/// タイマーを設定したメッセージの id (重複なし)
+/// import で設定したタイマーは同じメッセージから複数設定されている
+fn source_message_uuids(messages: &[Message]) -> BTreeSet<String> {
+    messages
+        .iter()
+        .map(|message| message.message_uuid.clone())
+        .collect()
+}